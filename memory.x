@@ -1,6 +1,7 @@
 /* Linker script for the STM32F103C8T6 */
 MEMORY
 {
-  FLASH : ORIGIN = 0x08000000, LENGTH = 64K
+  /* the last 4 pages are used to store the settings, see src/flash.rs */
+  FLASH : ORIGIN = 0x08000000, LENGTH = 60K
   RAM : ORIGIN = 0x20000000, LENGTH = 20K
 }
@@ -1,54 +1,681 @@
-use crate::datetime::{DateTime, DayOfWeek};
+use crate::chime::{Chime, ChimeConfig};
+use crate::datetime::{self, Country, Date, DateTime, DayOfWeek, Location, SunEvent};
+use crate::sleep::SleepConfig;
+use crate::store::{Flash, Store};
 use bitflags::bitflags;
 use core::fmt;
+use heapless::{consts::*, ArrayLength, Vec};
+
+pub type NbAlarms = U20;
+pub type NbExceptions = U8;
+/// Maximum capacity of an `AlarmManager`, the alarms of an `Exception`
+/// being the bits of an `u32`.
+pub const MAX_ALARMS: usize = 32;
+
+/// Longest forward clock jump for which the skipped alarms ring,
+/// unit: s.
+pub const MAX_CATCH_UP: u32 = 60 * 60;
+/// Alarms ringing within this interval are in conflict, unit: min.
+pub const CONFLICT_MIN: u32 = 5;
+/// Number of days from today where the conflicts are searched.
+const CONFLICT_DAYS: u32 = 28;
+/// Longest nap, unit: min.
+pub const MAX_NAP: u8 = 180;
+/// Number of alarm profiles, each one being a separate set of alarms
+/// and exceptions, only the active one being in an `AlarmManager`.
+pub const NB_PROFILES: usize = 4;
+/// Tag of a nap encoded in an `u32`, never decoded as an alarm by
+/// `Alarm::try_from`.
+const NAP_TAG: u32 = 0xf << 28;
 
 #[derive(Debug, Clone)]
-pub struct AlarmManager {
-    pub alarms: [Alarm; 5],
+pub struct AlarmManager<N = NbAlarms>
+where
+    N: ArrayLength<Alarm>,
+{
+    /// the alarms of the active profile
+    pub alarms: Vec<Alarm, N>,
+    /// days where the repeating alarms of the active profile don't
+    /// ring
+    pub exceptions: Vec<Exception, NbExceptions>,
+    pub snooze_config: SnoozeConfig,
+    pub sleep_config: SleepConfig,
+    pub chime_config: ChimeConfig,
+    /// no alarm rings until the end of this date, the nap excepted
+    pub suspended_until: Option<Date>,
+    /// names of the profiles, empty for a default name
+    pub profiles: [Label; NB_PROFILES],
+    /// the profile of `alarms` and `exceptions`, the alarms of the
+    /// other profiles being only in the store
+    pub active_profile: u8,
+    /// the place giving the sunrise and sunset, the alarms relative
+    /// to them not ringing if `None`
+    pub location: Option<Location>,
+    /// epoch of the next ring of the snoozed alarm
+    snooze: Option<u32>,
+    nb_snoozes: u8,
+    /// epoch of the ring of the nap, a temporary alarm outside of
+    /// `alarms`
+    nap: Option<u32>,
+    /// index of the last alarm that rang, `None` for the nap
+    alarm: Option<usize>,
+    ringtone: Ringtone,
+    ring_policy: RingPolicy,
+    label: Label,
+    /// epoch of the beginning of the current ring
+    ringing: Option<u32>,
+    /// epoch of the beginning of the last missed ring
+    missed: Option<u32>,
+    /// epoch of the last call to `must_ring`
+    last_checked: Option<u32>,
 }
-impl Default for AlarmManager {
+impl<N: ArrayLength<Alarm>> Default for AlarmManager<N> {
     fn default() -> Self {
+        let mut alarms = Vec::new();
+        while alarms.push(Alarm::default()).is_ok() {}
+        assert!(alarms.len() <= MAX_ALARMS, "too many alarms");
         Self {
-            alarms: [
-                Alarm::default(),
-                Alarm::default(),
-                Alarm::default(),
-                Alarm::default(),
-                Alarm::default(),
-            ],
+            alarms,
+            exceptions: Vec::new(),
+            snooze_config: SnoozeConfig::default(),
+            sleep_config: SleepConfig::default(),
+            chime_config: ChimeConfig::default(),
+            suspended_until: None,
+            profiles: [Label::default(); NB_PROFILES],
+            active_profile: 0,
+            location: None,
+            snooze: None,
+            nb_snoozes: 0,
+            nap: None,
+            alarm: None,
+            ringtone: Ringtone::default(),
+            ring_policy: RingPolicy::default(),
+            label: Label::default(),
+            ringing: None,
+            missed: None,
+            last_checked: None,
         }
     }
 }
-impl AlarmManager {
-    pub fn must_ring(&mut self, datetime: &DateTime) -> bool {
+impl<N: ArrayLength<Alarm>> AlarmManager<N> {
+    /// Restores the alarms saved in the store.
+    pub fn load<F: Flash>(store: &Store<F>) -> Self {
+        let p = match store.get(ACTIVE_PROFILE_KEY) {
+            Some(p) if p < NB_PROFILES as u32 => p as u8,
+            _ => 0,
+        };
+        let mut manager = Self::load_profile(store, p);
+        if let Some(config) = store.get(SLEEP_KEY).and_then(SleepConfig::try_from) {
+            manager.sleep_config = config;
+        }
+        if let Some(config) = store.get(CHIME_KEY).and_then(ChimeConfig::try_from) {
+            manager.chime_config = config;
+        }
+        manager.suspended_until = match store.get(SUSPENSION_KEY) {
+            None | Some(0) => None,
+            Some(days) => Some(Date::new(days)),
+        };
+        for (p, name) in manager.profiles.iter_mut().enumerate() {
+            let (low, high) = (profile_key(p, 0), profile_key(p, 1));
+            let (low, high) = (store.get(low).unwrap_or(0), store.get(high).unwrap_or(0));
+            *name = Label::try_from(low, high).unwrap_or_default();
+        }
+        if let Some(high) = store.get(LOCATION_KEY + 1) {
+            let low = store.get(LOCATION_KEY).unwrap_or(0);
+            manager.location = Location::from_words([low, high]);
+        }
+        manager
+    }
+    /// A default manager with the alarms and the exceptions of the
+    /// profile `p` saved in the store, to give to `activate_profile`.
+    pub fn load_profile<F: Flash>(store: &Store<F>, p: u8) -> Self {
+        let mut manager = Self {
+            active_profile: p,
+            ..Self::default()
+        };
+        let p = usize::from(p);
+        for (i, alarm) in manager.alarms.iter_mut().enumerate() {
+            let mut words = [None; ALARM_WORDS];
+            for (w, word) in words.iter_mut().enumerate() {
+                *word = store.get(alarm_key(p, i, w));
+            }
+            if let Some(a) = Alarm::from_words(&words) {
+                *alarm = a;
+            }
+        }
+        for i in 0..manager.exceptions.capacity() {
+            let mut words = [None; EXCEPTION_WORDS];
+            for (w, word) in words.iter_mut().enumerate() {
+                *word = store.get(exception_key(p, i, w));
+            }
+            if let Some(e) = Exception::from_words(&words) {
+                manager.exceptions.push(e).unwrap();
+            }
+        }
+        manager
+    }
+    /// Replaces the alarms and the exceptions by the ones of the
+    /// profile loaded by `load_profile`, the current ones having to
+    /// be saved before. A snoozed alarm of the previous profile is
+    /// dismissed.
+    pub fn activate_profile(&mut self, profile: Self) {
+        self.alarms = profile.alarms;
+        self.exceptions = profile.exceptions;
+        self.active_profile = profile.active_profile;
+        if self.alarm.is_some() {
+            self.dismiss();
+        }
+    }
+    /// The records to save in a store to persist the alarms and the
+    /// exceptions of the active profile, the sleep and the chime
+    /// configurations, the suspension, the profiles and the location.
+    /// The store keeps the alarms of the other profiles.
+    pub fn records<'a>(&'a self) -> impl Iterator<Item = (u16, u32)> + Clone + 'a {
+        let p = usize::from(self.active_profile);
+        let alarms = self.alarms.iter().enumerate().flat_map(move |(i, a)| {
+            let words = a.as_words();
+            (0..ALARM_WORDS).map(move |w| (alarm_key(p, i, w), words[w]))
+        });
+        // the free slots are saved to remove the deleted exceptions
+        let exceptions = (0..self.exceptions.capacity()).flat_map(move |i| {
+            let words = self
+                .exceptions
+                .get(i)
+                .map_or([0; EXCEPTION_WORDS], |e| e.as_words());
+            (0..EXCEPTION_WORDS).map(move |w| (exception_key(p, i, w), words[w]))
+        });
+        let sleep = core::iter::once((SLEEP_KEY, self.sleep_config.as_u32()));
+        let chime = core::iter::once((CHIME_KEY, self.chime_config.as_u32()));
+        let days = self.suspended_until.and_then(|d| d.to_days()).unwrap_or(0);
+        let suspension = core::iter::once((SUSPENSION_KEY, days));
+        let profiles = self.profiles.iter().enumerate().flat_map(|(p, name)| {
+            (0..2).map(move |half| (profile_key(p, half), name.as_u32(half)))
+        });
+        let active_profile = u32::from(self.active_profile);
+        let active_profile = core::iter::once((ACTIVE_PROFILE_KEY, active_profile));
+        let location = self.location.map_or([0; 2], |l| l.as_words());
+        let location = (0..2).map(move |w| (LOCATION_KEY + w as u16, location[w]));
+        alarms
+            .chain(exceptions)
+            .chain(sleep)
+            .chain(chime)
+            .chain(suspension)
+            .chain(profiles)
+            .chain(active_profile)
+            .chain(location)
+    }
+    /// Returns true if the repeating alarm `i` must not ring on `date`.
+    pub fn is_exception(&self, i: usize, date: Date) -> bool {
+        self.exceptions
+            .iter()
+            .any(|e| e.contains(date) && e.applies_to(i))
+    }
+    /// Removes the exception `i`, keeping the order of the others.
+    pub fn remove_exception(&mut self, i: usize) {
+        if i < self.exceptions.len() {
+            self.exceptions[i..].rotate_left(1);
+            self.exceptions.pop();
+        }
+    }
+    /// Returns true if the alarms are suspended on `date`.
+    pub fn is_suspended(&self, date: Date) -> bool {
+        matches!(self.suspended_until, Some(until) if date <= until)
+    }
+    /// Returns true if the alarm `i` must not ring on `date`, because
+    /// of the suspension, or of an exception or a public holiday for
+    /// a repeating alarm.
+    fn is_skipped(&self, i: usize, date: Date) -> bool {
+        let alarm = &self.alarms[i];
+        let is_holiday = match alarm.holidays {
+            Some(country) => country.is_holiday(date),
+            None => false,
+        };
+        self.is_suspended(date)
+            || alarm.is_repeating() && (is_holiday || self.is_exception(i, date))
+    }
+    /// Returns the alarms beginning to ring now. The alarms since the
+    /// previous call are also evaluated, catching up the seconds
+    /// skipped by a late call or a forward clock jump of at most
+    /// `MAX_CATCH_UP`. After a bigger forward jump or a backward
+    /// jump, only the current second is evaluated, the alarms of a
+    /// period lived again ringing again.
+    pub fn must_ring(&mut self, datetime: &DateTime) -> Option<Rings> {
+        let now = datetime.to_epoch()?;
+        let from = match self.last_checked {
+            Some(last) if last <= now && now - last <= MAX_CATCH_UP => last,
+            _ => now - 1,
+        };
+        self.last_checked = Some(now);
+        self.rings_between(from, now)
+    }
+    /// Returns the alarms ringing after `from` and until `to` included
+    /// (epochs), the ring beginning at `to`. Rings only one time even
+    /// if several alarms or occurrences are in the interval.
+    pub fn rings_between(&mut self, from: u32, to: u32) -> Option<Rings> {
+        let mut rings = Rings::default();
+        match self.snooze {
+            Some(snooze) if to >= snooze => {
+                self.snooze = None;
+                rings.add(self.alarm);
+            }
+            _ => {}
+        }
+        match self.nap {
+            // a nap restored long after its end doesn't ring anymore
+            Some(nap) if to >= nap && to - nap > MAX_CATCH_UP => self.nap = None,
+            Some(nap) if to >= nap => {
+                self.nap = None;
+                self.alarm = None;
+                self.ringtone = Ringtone::default();
+                self.ring_policy = RingPolicy::default();
+                self.label = nap_label();
+                rings.add(None);
+            }
+            _ => {}
+        }
+        let mut alarm_ring = false;
+        for i in 0..self.alarms.len() {
+            let mut t = DateTime::new(from);
+            let location = self.location;
+            while let Some((date, hour, min)) = self.alarms[i].next_occurrence(&t, location) {
+                t = datetime_at(date, hour, min);
+                match t.to_epoch() {
+                    Some(epoch) if epoch <= to => {}
+                    _ => break,
+                }
+                if self.is_skipped(i, date) {
+                    continue;
+                }
+                let alarm = &mut self.alarms[i];
+                if alarm.must_ring(&t, location) {
+                    alarm_ring = true;
+                    rings.add(Some(i));
+                    self.alarm = Some(i);
+                    self.ringtone = alarm.ringtone;
+                    self.ring_policy = alarm.ring_policy;
+                    self.label = alarm.label;
+                }
+            }
+        }
+        if alarm_ring {
+            // a new alarm supersedes the snoozed one
+            self.snooze = None;
+            self.nb_snoozes = 0;
+        }
+        if rings.is_empty() {
+            return None;
+        }
+        self.ringing = Some(to);
+        Some(rings)
+    }
+    /// Records the ringing alarm as missed when it has rung for its
+    /// whole duration. Returns true if it has just been missed.
+    pub fn check_missed(&mut self, datetime: &DateTime) -> bool {
+        match (self.ringing, datetime.to_epoch()) {
+            (Some(start), Some(now)) if now >= start + self.ring_policy.duration_sec() => {
+                self.ringing = None;
+                self.missed = Some(start);
+                true
+            }
+            _ => false,
+        }
+    }
+    pub fn ringing(&self) -> Option<DateTime> {
+        self.ringing.map(DateTime::new)
+    }
+    pub fn missed(&self) -> Option<DateTime> {
+        self.missed.map(DateTime::new)
+    }
+    pub fn acknowledge_missed(&mut self) {
+        self.missed = None;
+    }
+    /// Snoozes the ringing alarm. Returns false if there is no
+    /// snooze left, the alarm being dismissed in this case.
+    pub fn snooze(&mut self, datetime: &DateTime) -> bool {
+        let now = match datetime.to_epoch() {
+            Some(now) => now,
+            None => return false,
+        };
+        if self.remaining_snoozes() == 0 {
+            self.dismiss();
+            return false;
+        }
+        let duration = self.snooze_config.duration(self.nb_snoozes);
+        self.nb_snoozes += 1;
+        self.ringing = None;
+        self.snooze = Some(now + u32::from(duration) * 60);
+        true
+    }
+    pub fn dismiss(&mut self) {
+        self.ringing = None;
+        self.snooze = None;
+        self.nb_snoozes = 0;
+    }
+    /// The ringtone of the last alarm that rang.
+    pub fn ringtone(&self) -> Ringtone {
+        self.ringtone
+    }
+    /// The ring policy of the last alarm that rang.
+    pub fn ring_policy(&self) -> RingPolicy {
+        self.ring_policy
+    }
+    /// The label of the last alarm that rang.
+    pub fn label(&self) -> Label {
+        self.label
+    }
+    pub fn snoozed_until(&self) -> Option<DateTime> {
+        self.snooze.map(DateTime::new)
+    }
+    pub fn remaining_snoozes(&self) -> u8 {
+        self.snooze_config
+            .max_snoozes
+            .saturating_sub(self.nb_snoozes)
+    }
+    /// Sets a nap ringing `minutes` after `datetime`, at most
+    /// `MAX_NAP`. 0 cancels the nap.
+    pub fn set_nap(&mut self, datetime: &DateTime, minutes: u8) {
+        let minutes = minutes.min(MAX_NAP);
+        self.nap = match minutes {
+            0 => None,
+            _ => nap_end(datetime, minutes).and_then(|dt| dt.to_epoch()),
+        };
+    }
+    pub fn nap(&self) -> Option<DateTime> {
+        self.nap.map(DateTime::new)
+    }
+    /// Encodes the nap in an `u32`, 0 if there is no nap.
+    pub fn nap_as_u32(&self) -> u32 {
+        match self.nap {
+            Some(epoch) => NAP_TAG | (epoch / 60),
+            None => 0,
+        }
+    }
+    /// Restores a nap encoded by `nap_as_u32`.
+    pub fn load_nap(&mut self, u: u32) {
+        if u & NAP_TAG == NAP_TAG {
+            self.nap = Some((u & !NAP_TAG) * 60);
+        }
+    }
+    pub fn next_ring(&self, dt: &DateTime) -> Option<NextRing> {
+        let snooze = self.snooze.map(|epoch| (epoch, None));
+        let nap = self.nap.map(|epoch| (epoch, None));
         self.alarms
-            .iter_mut()
-            .map(|am| am.must_ring(datetime) as u8)
-            .sum::<u8>()
-            > 0
-    }
-    pub fn next_ring(&self, dt: &DateTime) -> Option<(DayOfWeek, u8, u8)> {
-        let mut min = None;
-        for cur in self.alarms.iter().filter_map(|a| a.next_ring(dt)) {
-            match min {
-                None => min = Some(cur),
-                Some(min_val) => {
-                    let cmp_value = |(dow, h, m)| {
-                        let mut days = (dow as u8 + 7 - dt.day_of_week as u8) % 7;
-                        let now_h = time(dt.hour, dt.min);
-                        let this_h = time(h, m);
-                        if dow == dt.day_of_week && this_h <= now_h {
-                            days += 7
-                        }
-                        u32::from(days) * 60 * 24 + this_h
-                    };
-                    if cmp_value(cur) < cmp_value(min_val) {
-                        min = Some(cur);
-                    }
+            .iter()
+            .enumerate()
+            .filter_map(|(i, a)| {
+                let is_skipped = |d| self.is_skipped(i, d);
+                let (date, hour, min) = a.next_ring_except(dt, self.location, is_skipped)?;
+                if self.is_suspended(date) {
+                    // a one time alarm
+                    return None;
                 }
+                Some((datetime_at(date, hour, min).to_epoch()?, Some(i)))
+            })
+            .chain(snooze)
+            .chain(nap)
+            .min_by_key(|&(epoch, _)| epoch)
+            .map(|(epoch, alarm)| NextRing {
+                datetime: DateTime::new(epoch),
+                epoch,
+                alarm,
+            })
+    }
+    /// Returns the index of the alarm having a pre-alarm now, if
+    /// any. Pre-alarms are reported every minute of their window,
+    /// except during a ring.
+    pub fn must_pre_ring(&self, datetime: &DateTime) -> Option<usize> {
+        let now = datetime.to_epoch()?;
+        if datetime.sec != 0 || self.ringing.is_some() {
+            return None;
+        }
+        self.alarms.iter().enumerate().position(|(i, a)| {
+            if a.pre_alarm == 0 {
+                return false;
+            }
+            let next = a.next_ring_except(datetime, self.location, |d| self.is_skipped(i, d));
+            let next = next.filter(|&(d, _, _)| !self.is_suspended(d));
+            match next.and_then(|(d, h, m)| datetime_at(d, h, m).to_epoch()) {
+                Some(epoch) => epoch - now <= u32::from(a.pre_alarm) * 60,
+                None => false,
+            }
+        })
+    }
+    /// Returns true if it is time to go to bed to sleep the sleep
+    /// target before the next ring.
+    pub fn must_remind_bedtime(&self, datetime: &DateTime) -> bool {
+        if !self.sleep_config.reminder || datetime.sec != 0 {
+            return false;
+        }
+        match (datetime.to_epoch(), self.next_ring(datetime)) {
+            (Some(now), Some(next)) => self.sleep_config.bedtime(next.epoch) == now,
+            _ => false,
+        }
+    }
+    /// The chime to play now, if any. Never chimes while an alarm is
+    /// ringing or snoozed.
+    pub fn must_chime(&self, datetime: &DateTime) -> Option<Chime> {
+        if self.ringing.is_some() || self.snooze.is_some() {
+            return None;
+        }
+        self.chime_config.chime_at(datetime)
+    }
+    /// Returns a conflict of `alarm`, saved as the alarm `i`, with
+    /// another enabled alarm. A duplicate is preferably reported, else
+    /// a ring near a ring of another alarm, from `today` to
+    /// `CONFLICT_DAYS` later.
+    pub fn conflict(&self, i: usize, alarm: &Alarm, today: Date) -> Option<Conflict> {
+        if !alarm.is_enable {
+            return None;
+        }
+        let others = || {
+            self.alarms
+                .iter()
+                .enumerate()
+                .filter(move |&(j, a)| j != i && a.is_enable)
+        };
+        if let Some((j, _)) = others().find(|(_, a)| a.is_duplicate_of(alarm)) {
+            return Some(Conflict::Duplicate(j));
+        }
+        let start = datetime_at(today, 0, 0).to_epoch()?;
+        let end = start + CONFLICT_DAYS * 24 * 3600;
+        let location = self.location;
+        let rings: Vec<u32, U32> = occurrences(alarm, location, start, end).collect();
+        let is_near = |e: u32| {
+            rings
+                .iter()
+                .any(|&r| core::cmp::max(r, e) - core::cmp::min(r, e) <= CONFLICT_MIN * 60)
+        };
+        others()
+            .find(|(_, a)| occurrences(a, location, start, end).any(is_near))
+            .map(|(j, _)| Conflict::Near(j))
+    }
+    /// The label of the alarm of `ring`.
+    pub fn label_of(&self, ring: &NextRing) -> Label {
+        match ring.alarm {
+            Some(i) => self.alarms[i].label,
+            None if self.nap == Some(ring.epoch) => nap_label(),
+            None => self.label,
+        }
+    }
+}
+
+/// The alarms beginning to ring together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rings {
+    /// bit i set if the alarm i rings, the snoozed alarm included,
+    /// an `AlarmManager` having at most `MAX_ALARMS` alarms
+    pub alarms: u32,
+    /// the nap, or the snoozed nap, rings
+    pub nap: bool,
+}
+impl Rings {
+    /// Adds the alarm `i`, `None` for the nap.
+    fn add(&mut self, i: Option<usize>) {
+        match i {
+            Some(i) => {
+                assert!(i < MAX_ALARMS, "too many alarms");
+                self.alarms |= 1 << i
             }
+            None => self.nap = true,
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.alarms == 0 && !self.nap
+    }
+    pub fn contains(&self, i: usize) -> bool {
+        i < MAX_ALARMS && self.alarms & 1 << i != 0
+    }
+    /// The indices of the ringing alarms.
+    pub fn alarms(&self) -> impl Iterator<Item = usize> {
+        let alarms = self.alarms;
+        (0..MAX_ALARMS).filter(move |&i| alarms & 1 << i != 0)
+    }
+}
+
+/// A conflict of an alarm with the given other alarm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    /// Same schedule.
+    Duplicate(usize),
+    /// Rings within `CONFLICT_MIN` minutes.
+    Near(usize),
+}
+
+/// The epochs of the rings of `alarm` from `from` until `to`, the
+/// skipped rings included.
+fn occurrences(
+    alarm: &Alarm,
+    location: Option<Location>,
+    from: u32,
+    to: u32,
+) -> impl Iterator<Item = u32> + '_ {
+    // the occurrences are strictly after `t`, rings at `from` being
+    // found from the previous minute
+    let mut t = DateTime::new(from.saturating_sub(60));
+    // at most one ring a day
+    let nb = if alarm.is_repeating() {
+        ((to - from) / (24 * 3600)) as usize + 1
+    } else {
+        1
+    };
+    core::iter::from_fn(move || {
+        let (date, hour, min) = alarm.next_occurrence(&t, location)?;
+        t = datetime_at(date, hour, min);
+        t.to_epoch().filter(|&epoch| epoch <= to)
+    })
+    .take(nb)
+}
+
+/// The next ring of an `AlarmManager`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NextRing {
+    pub datetime: DateTime,
+    /// epoch of `datetime`
+    pub epoch: u32,
+    /// index of the alarm, `None` for the snoozed alarm or the nap
+    pub alarm: Option<usize>,
+}
+
+/// The ring of a nap of `minutes` beginning at `datetime`, at the
+/// beginning of a minute as the alarms.
+pub fn nap_end(datetime: &DateTime, minutes: u8) -> Option<DateTime> {
+    let epoch = datetime.to_epoch()? + u32::from(minutes) * 60;
+    Some(DateTime::new(epoch + (60 - epoch % 60) % 60))
+}
+
+fn nap_label() -> Label {
+    Label::new("Nap").unwrap()
+}
+
+fn alarm_key(profile: usize, i: usize, word: usize) -> u16 {
+    0x1000 | (word as u16) << 8 | (profile as u16) << 5 | i as u16
+}
+fn exception_key(profile: usize, i: usize, word: usize) -> u16 {
+    0x2000 | (word as u16) << 8 | (profile as u16) << 4 | i as u16
+}
+const SLEEP_KEY: u16 = 0x3000;
+const CHIME_KEY: u16 = 0x3001;
+/// The days since 1970-01-01 of the end of the suspension, 0 if none.
+const SUSPENSION_KEY: u16 = 0x3002;
+const ACTIVE_PROFILE_KEY: u16 = 0x3003;
+/// The two words of the location.
+const LOCATION_KEY: u16 = 0x3004;
+fn profile_key(p: usize, half: usize) -> u16 {
+    0x3100 | (half as u16) << 4 | p as u16
+}
+
+/// Number of `u32` needed to save an exception.
+const EXCEPTION_WORDS: usize = 3;
+
+/// A range of days where the repeating alarms don't ring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exception {
+    pub first: Date,
+    /// included
+    pub last: Date,
+    /// bit i set to suppress the alarm i, `ALL_ALARMS` to suppress
+    /// every alarm, see `MAX_ALARMS`
+    pub alarms: u32,
+}
+impl Exception {
+    pub const ALL_ALARMS: u32 = !0;
+
+    /// An exception for every alarm.
+    pub fn new(first: Date, last: Date) -> Self {
+        Self {
+            first,
+            last,
+            alarms: Self::ALL_ALARMS,
         }
-        min
+    }
+    pub fn contains(&self, date: Date) -> bool {
+        self.first <= date && date <= self.last
+    }
+    pub fn applies_to(&self, i: usize) -> bool {
+        self.alarms == Self::ALL_ALARMS || i < 32 && self.alarms & 1 << i != 0
+    }
+    fn as_words(&self) -> [u32; EXCEPTION_WORDS] {
+        let first = self.first.to_days().unwrap_or(0);
+        let last = self.last.to_days().unwrap_or(0);
+        [first, last, self.alarms]
+    }
+    fn from_words(words: &[Option<u32>; EXCEPTION_WORDS]) -> Option<Self> {
+        let (first, last) = (words[0]?, words[1]?);
+        // 0 is a free slot
+        if first == 0 || first > last {
+            return None;
+        }
+        Some(Self {
+            first: Date::new(first),
+            last: Date::new(last),
+            alarms: words[2]?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnoozeConfig {
+    /// unit: min
+    pub duration: u8,
+    pub max_snoozes: u8,
+    /// duration removed at each new snooze, unit: min
+    pub shortening: u8,
+}
+impl Default for SnoozeConfig {
+    fn default() -> Self {
+        Self {
+            duration: 9,
+            max_snoozes: 3,
+            shortening: 0,
+        }
+    }
+}
+impl SnoozeConfig {
+    /// Duration of the snooze after `nb_snoozes` previous snoozes,
+    /// never shorter than a minute.
+    pub fn duration(&self, nb_snoozes: u8) -> u8 {
+        let shortening = self.shortening.saturating_mul(nb_snoozes);
+        core::cmp::max(1, self.duration.saturating_sub(shortening))
     }
 }
 
@@ -58,6 +685,638 @@ pub struct Alarm {
     hour: u8,
     min: u8,
     pub mode: Mode,
+    date: Option<Date>,
+    pub ringtone: Ringtone,
+    pub ring_policy: RingPolicy,
+    pub label: Label,
+    /// The next occurrence will not ring.
+    pub skip_next: bool,
+    recurrence: Option<Recurrence>,
+    rotation: Option<Rotation>,
+    /// pre-alarm every minute before the ring, unit: min, 0 for none
+    pub pre_alarm: u8,
+    /// a repeating alarm does not ring on the public holidays of
+    /// this country
+    pub holidays: Option<Country>,
+    solar: Option<Solar>,
+}
+
+/// Longest pre-alarm, unit: min.
+pub const MAX_PRE_ALARM: u8 = 30;
+
+/// Longest offset from the sunrise or the sunset, unit: min.
+pub const MAX_SOLAR_OFFSET: i16 = 180;
+
+/// A ring time relative to the sunrise or the sunset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Solar {
+    pub event: SunEvent,
+    /// unit: min, negative before the event
+    pub offset: i16,
+}
+impl Solar {
+    /// The ring time of `date`, `None` if the sun doesn't rise or set
+    /// this day, or if the offset moves the ring to another day.
+    pub fn time_on(&self, date: Date, location: Location) -> Option<(u8, u8)> {
+        let (hour, min) = datetime::sun_event(date, location, self.event)?;
+        let t = time(hour, min) as i32 + i32::from(self.offset);
+        if !(0..24 * 60).contains(&t) {
+            return None;
+        }
+        Some(((t / 60) as u8, (t % 60) as u8))
+    }
+    fn as_u32(&self) -> u32 {
+        let event = match self.event {
+            SunEvent::Sunrise => 1,
+            SunEvent::Sunset => 2,
+        };
+        event | ((self.offset + 512) as u32) << 2
+    }
+    /// Decodes `as_u32`, 0 being no solar time.
+    fn from_u32(u: u32) -> Option<Option<Self>> {
+        if u == 0 {
+            return Some(None);
+        }
+        let event = match u & 3 {
+            1 => SunEvent::Sunrise,
+            2 => SunEvent::Sunset,
+            _ => return None,
+        };
+        let offset = (u >> 2) as i16 - 512;
+        if offset.abs() > MAX_SOLAR_OFFSET {
+            return None;
+        }
+        Some(Some(Self { event, offset }))
+    }
+    /// Parses the `Display` of a solar time, as "sunrise" or
+    /// "sunset-30".
+    fn parse(s: &str) -> Option<Self> {
+        let (event, offset) = if let Some(offset) = s.strip_prefix("sunrise") {
+            (SunEvent::Sunrise, offset)
+        } else {
+            (SunEvent::Sunset, s.strip_prefix("sunset")?)
+        };
+        let offset = match offset.chars().next() {
+            None => 0,
+            Some('+') | Some('-') => offset.parse().ok()?,
+            Some(_) => return None,
+        };
+        if i16::abs(offset) > MAX_SOLAR_OFFSET {
+            return None;
+        }
+        Some(Self { event, offset })
+    }
+}
+impl fmt::Display for Solar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.event)?;
+        if self.offset != 0 {
+            write!(f, "{:+}", self.offset)?;
+        }
+        Ok(())
+    }
+}
+
+/// Number of `u32` needed to save an alarm.
+pub const ALARM_WORDS: usize = 11;
+const ROTATION_WORDS: usize = 4;
+
+/// Number of slots of a rotation.
+pub const NB_SLOTS: usize = 6;
+/// Maximum number of days of a slot.
+pub const MAX_SLOT_DAYS: u8 = 31;
+
+/// Consecutive days of a rotation ringing at the same time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Slot {
+    /// 0 for an unused slot
+    pub nb_days: u8,
+    /// (hour, min), `None` to not ring these days
+    pub time: Option<(u8, u8)>,
+}
+impl Slot {
+    fn as_u16(&self) -> u16 {
+        let (hour, min) = self.time.unwrap_or((31, 0));
+        u16::from(self.nb_days) | u16::from(hour) << 5 | u16::from(min) << 10
+    }
+    fn from_u16(u: u16) -> Self {
+        let (hour, min) = ((u >> 5 & 0x1f) as u8, (u >> 10) as u8);
+        Self {
+            nb_days: (u & 0x1f) as u8,
+            time: if hour == 31 { None } else { Some((hour, min)) },
+        }
+    }
+}
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.time {
+            Some((h, m)) => write!(f, "{}d {:02}:{:02}", self.nb_days, h, m),
+            None => write!(f, "{}d off", self.nb_days),
+        }
+    }
+}
+
+/// A shift work rotation: the slots follow each other from the start
+/// date, and the cycle begins again after the last used slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rotation {
+    pub start: Date,
+    pub slots: [Slot; NB_SLOTS],
+}
+impl Rotation {
+    /// A rotation with all the slots unused.
+    pub fn new(start: Date) -> Self {
+        Self {
+            start,
+            slots: [Slot::default(); NB_SLOTS],
+        }
+    }
+    /// Number of days of the cycle.
+    pub fn nb_days(&self) -> u32 {
+        self.slots.iter().map(|s| u32::from(s.nb_days)).sum()
+    }
+    pub fn is_valid(&self) -> bool {
+        let valid_slot = |s: &Slot| {
+            let valid_time = match s.time {
+                None => true,
+                Some((h, m)) => h < 24 && m < 60,
+            };
+            s.nb_days <= MAX_SLOT_DAYS && valid_time
+        };
+        self.start.is_valid()
+            && self.start.year >= 2000
+            && self.start.year < 2128
+            && self.nb_days() != 0
+            && self.slots.iter().all(valid_slot)
+    }
+    /// The ring time of `date`, `None` if the rotation doesn't ring
+    /// this day.
+    pub fn time_on(&self, date: Date) -> Option<(u8, u8)> {
+        let days = date.to_days()?.checked_sub(self.start.to_days()?)?;
+        let mut pos = days % self.nb_days();
+        for slot in self.slots.iter() {
+            let nb_days = u32::from(slot.nb_days);
+            if pos < nb_days {
+                return slot.time;
+            }
+            pos -= nb_days;
+        }
+        None
+    }
+    fn as_words(&self) -> [u32; ROTATION_WORDS] {
+        let slots = |i: usize| {
+            u32::from(self.slots[i].as_u16()) | u32::from(self.slots[i + 1].as_u16()) << 16
+        };
+        let start = self.start.to_days().unwrap_or(0);
+        [start, slots(0), slots(2), slots(4)]
+    }
+    fn from_words(words: &[u32; ROTATION_WORDS]) -> Option<Self> {
+        let mut res = Self::new(Date::new(words[0]));
+        for (i, slot) in res.slots.iter_mut().enumerate() {
+            *slot = Slot::from_u16((words[1 + i / 2] >> (16 * (i % 2))) as u16);
+        }
+        if res.is_valid() {
+            Some(res)
+        } else {
+            None
+        }
+    }
+}
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for slot in self.slots.iter().filter(|s| s.nb_days != 0) {
+            if !first {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}", slot)?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+/// A subset of the iCalendar recurrence rules (RRULE). Without
+/// recurrence, a repeating alarm rings every week on the days of its
+/// `Mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recurrence {
+    /// FREQ=DAILY;INTERVAL=interval, starting at start
+    Daily { interval: u8, start: Date },
+    /// FREQ=WEEKLY;INTERVAL=interval on the days of the `Mode`, the
+    /// weeks being counted from the week of start
+    Weekly { interval: u8, start: Date },
+    /// FREQ=MONTHLY;BYMONTHDAY=n, negative values counting from the
+    /// end of the month (-1 being the last day)
+    MonthDay(i8),
+    /// FREQ=MONTHLY;BYDAY=<n><dow>, negative values counting from
+    /// the end of the month (-1 being the last one)
+    MonthWeekDay(i8, DayOfWeek),
+}
+impl Recurrence {
+    pub fn is_valid(&self) -> bool {
+        use self::Recurrence::*;
+        match *self {
+            Daily { interval, start } | Weekly { interval, start } => {
+                interval != 0 && start.is_valid() && start.year >= 2000 && start.year < 2128
+            }
+            MonthDay(n) => n != 0 && (-31..=31).contains(&n),
+            MonthWeekDay(n, _) => n != 0 && (-5..=5).contains(&n),
+        }
+    }
+    /// Returns true if the `Mode` gives the days of the week.
+    pub fn uses_mode(&self) -> bool {
+        matches!(self, Recurrence::Weekly { .. })
+    }
+    /// Returns true if the rule matches `date`.
+    pub fn contains(&self, date: Date, mode: Mode) -> bool {
+        use self::Recurrence::*;
+        let dow = date.day_of_week();
+        match *self {
+            Daily { interval, start } => match (date.to_days(), start.to_days()) {
+                (Some(d), Some(s)) => d >= s && (d - s) % u32::from(interval) == 0,
+                _ => false,
+            },
+            Weekly { interval, start } => {
+                let monday = start.to_days().map(|s| s - start.day_of_week() as u32);
+                match (date.to_days(), monday) {
+                    (Some(d), Some(m)) => {
+                        d >= m && (d - m) / 7 % u32::from(interval) == 0 && mode.contains_dow(dow)
+                    }
+                    _ => false,
+                }
+            }
+            MonthDay(n) => {
+                let nb_days = date.nb_days_in_month() as i8;
+                let day = if n > 0 { n } else { nb_days + 1 + n };
+                date.day as i8 == day
+            }
+            MonthWeekDay(n, d) => {
+                let nth = if n > 0 {
+                    (date.day as i8 - 1) / 7 + 1
+                } else {
+                    -((date.nb_days_in_month() - date.day) as i8 / 7 + 1)
+                };
+                dow == d && nth == n
+            }
+        }
+    }
+    /// The first day from `from` matching the rule, computed without
+    /// walking the days for the daily and weekly rules.
+    pub fn next_day(&self, from: Date, mode: Mode) -> Option<Date> {
+        use self::Recurrence::*;
+        match *self {
+            Daily { interval, start } => {
+                let interval = u32::from(interval);
+                let (from, start) = (from.to_days()?, start.to_days()?);
+                let d = core::cmp::max(from, start);
+                Some(Date::new(
+                    d + (interval - (d - start) % interval) % interval,
+                ))
+            }
+            Weekly { interval, start } => {
+                let monday = start.to_days()? - start.day_of_week() as u32;
+                let interval = u32::from(interval) * 7;
+                let mut d = core::cmp::max(from.to_days()?, monday);
+                // the monday of the next ringing week
+                let offset = (d - monday) % interval;
+                if offset >= 7 {
+                    d += interval - offset;
+                }
+                // the end of a ringing week, then the whole next one
+                for _ in 0..14 {
+                    let offset = (d - monday) % interval;
+                    if offset < 7 && mode.contains_dow(DayOfWeek::from_days_since_epoch(d)) {
+                        return Some(Date::new(d));
+                    }
+                    d += if offset == 6 { interval - 6 } else { 1 };
+                }
+                None
+            }
+            MonthDay(_) | MonthWeekDay(..) => {
+                // a valid rule matches at least once a year
+                let mut date = from;
+                for _ in 0..366 {
+                    if self.contains(date, mode) {
+                        return Some(date);
+                    }
+                    date = date.next();
+                }
+                None
+            }
+        }
+    }
+    pub fn as_u32(&self) -> u32 {
+        use self::Recurrence::*;
+        let days = |d: Date| d.to_days().unwrap_or(0) << 8;
+        match *self {
+            Daily { interval, start } => 1 << 29 | u32::from(interval) | days(start),
+            Weekly { interval, start } => 2 << 29 | u32::from(interval) | days(start),
+            MonthDay(n) => 3 << 29 | u32::from(n as u8),
+            MonthWeekDay(n, dow) => 4 << 29 | u32::from(n as u8) | (dow as u32) << 8,
+        }
+    }
+    pub fn try_from(u: u32) -> Option<Self> {
+        use self::Recurrence::*;
+        let start = Date::new(u >> 8 & 0xffff);
+        let res = match u >> 29 {
+            1 => Daily {
+                interval: u as u8,
+                start,
+            },
+            2 => Weekly {
+                interval: u as u8,
+                start,
+            },
+            3 => MonthDay(u as i8),
+            4 => MonthWeekDay(u as i8, dow_from_u8((u >> 8) as u8)?),
+            _ => return None,
+        };
+        if res.is_valid() {
+            Some(res)
+        } else {
+            None
+        }
+    }
+}
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Recurrence::*;
+        match *self {
+            Daily { interval: 1, .. } => f.write_str("every day"),
+            Daily { interval, .. } => write!(f, "every {} days", interval),
+            Weekly { interval: 1, .. } => f.write_str("every week"),
+            Weekly { interval, .. } => write!(f, "every {} weeks", interval),
+            MonthDay(n) => write!(f, "monthly, {} day", Nth(n)),
+            MonthWeekDay(n, dow) => {
+                let dow = VEC_DAY_OF_WEEK_SHORT_NAME[dow as usize].1;
+                write!(f, "monthly, {}{}", Nth(n), dow)
+            }
+        }
+    }
+}
+
+/// Formats an ordinal, negative values counting from the end.
+struct Nth(i8);
+impl fmt::Display for Nth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let n = self.0.abs();
+        match n {
+            1 if self.0 < 0 => return f.write_str("last"),
+            1 => f.write_str("1st")?,
+            2 => f.write_str("2nd")?,
+            3 => f.write_str("3rd")?,
+            _ => write!(f, "{}th", n)?,
+        }
+        if self.0 < 0 {
+            f.write_str(" last")?;
+        }
+        Ok(())
+    }
+}
+
+fn dow_from_u8(u: u8) -> Option<DayOfWeek> {
+    use self::DayOfWeek::*;
+    match u {
+        0 => Some(Monday),
+        1 => Some(Tuesday),
+        2 => Some(Wednesday),
+        3 => Some(Thursday),
+        4 => Some(Friday),
+        5 => Some(Saturday),
+        6 => Some(Sunday),
+        _ => None,
+    }
+}
+
+/// The scores of `pwm_speaker::songs`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ringtone {
+    SoWhat,
+    MarioThemeIntro,
+    ThirdKind,
+    FrereJacques,
+}
+impl Default for Ringtone {
+    fn default() -> Self {
+        Ringtone::SoWhat
+    }
+}
+impl Ringtone {
+    const NB: u8 = 4;
+    pub fn next(self) -> Self {
+        Self::from_u8((self as u8 + 1) % Self::NB).unwrap_or_default()
+    }
+    pub fn prev(self) -> Self {
+        Self::from_u8((self as u8 + Self::NB - 1) % Self::NB).unwrap_or_default()
+    }
+    /// Parses the name given by `as_word`.
+    fn parse(s: &str) -> Option<Self> {
+        Self::from_u8(RINGTONE_WORDS.iter().position(|&w| w == s)? as u8)
+    }
+    /// The name of the ringtone in the text syntax of the alarms.
+    fn as_word(self) -> &'static str {
+        RINGTONE_WORDS[self as usize]
+    }
+    fn from_u8(u: u8) -> Option<Self> {
+        use self::Ringtone::*;
+        match u {
+            0 => Some(SoWhat),
+            1 => Some(MarioThemeIntro),
+            2 => Some(ThirdKind),
+            3 => Some(FrereJacques),
+            _ => None,
+        }
+    }
+}
+static RINGTONE_WORDS: [&str; Ringtone::NB as usize] =
+    ["so-what", "mario", "third-kind", "frere-jacques"];
+
+/// What must be done to dismiss a ringing alarm, preventing a
+/// half-asleep cancellation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Challenge {
+    /// A press on cancel.
+    Press,
+    /// Cancel held for a few seconds.
+    Hold,
+    /// A displayed sequence of buttons to enter.
+    Sequence,
+    /// An addition to solve.
+    Arithmetic,
+}
+impl Default for Challenge {
+    fn default() -> Self {
+        Challenge::Press
+    }
+}
+impl Challenge {
+    pub fn next(self) -> Self {
+        use self::Challenge::*;
+        match self {
+            Press => Hold,
+            Hold => Sequence,
+            Sequence => Arithmetic,
+            Arithmetic => Press,
+        }
+    }
+    fn from_u8(u: u8) -> Option<Self> {
+        use self::Challenge::*;
+        match u {
+            0 => Some(Press),
+            1 => Some(Hold),
+            2 => Some(Sequence),
+            3 => Some(Arithmetic),
+            _ => None,
+        }
+    }
+}
+impl fmt::Display for Challenge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Challenge::*;
+        f.write_str(match self {
+            Press => "press cancel",
+            Hold => "hold cancel",
+            Sequence => "sequence",
+            Arithmetic => "arithmetic",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RingPolicy {
+    /// unit: min
+    pub duration: u8,
+    /// unit: min, 0 to never escalate
+    pub escalate_after: u8,
+    pub escalation: Ringtone,
+    pub challenge: Challenge,
+}
+impl Default for RingPolicy {
+    fn default() -> Self {
+        Self {
+            duration: 10,
+            escalate_after: 0,
+            escalation: Ringtone::MarioThemeIntro,
+            challenge: Challenge::Press,
+        }
+    }
+}
+impl RingPolicy {
+    pub fn duration_sec(&self) -> u32 {
+        u32::from(self.duration) * 60
+    }
+    /// The ringtone to play after ringing `elapsed_sec` seconds,
+    /// `None` if the ring is over.
+    pub fn ringtone_at(&self, ringtone: Ringtone, elapsed_sec: u32) -> Option<Ringtone> {
+        if elapsed_sec >= self.duration_sec() {
+            None
+        } else if self.escalate_after != 0 && elapsed_sec >= u32::from(self.escalate_after) * 60 {
+            Some(self.escalation)
+        } else {
+            Some(ringtone)
+        }
+    }
+    pub fn as_u32(&self) -> u32 {
+        u32::from(self.duration)
+            | u32::from(self.escalate_after) << 8
+            | (self.escalation as u32) << 16
+            | (self.challenge as u32) << 20
+            | 1 << 24
+    }
+    pub fn try_from(u: u32) -> Option<Self> {
+        if u >> 24 != 1 || u as u8 == 0 {
+            return None;
+        }
+        Some(Self {
+            duration: u as u8,
+            escalate_after: (u >> 8) as u8,
+            escalation: Ringtone::from_u8((u >> 16) as u8 & 0xf)?,
+            challenge: Challenge::from_u8((u >> 20) as u8 & 0xf)?,
+        })
+    }
+}
+
+/// Characters usable in a label, 6 bits each.
+static LABEL_CHARS: &[u8; 64] = b" ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-";
+/// Number of characters of a label.
+pub const LABEL_LEN: usize = 8;
+
+/// A short name for an alarm, as "Work" or "Gym". The characters
+/// are stored as indices in `LABEL_CHARS`, 0 being a space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Label([u8; LABEL_LEN]);
+impl Label {
+    /// Returns `None` if `s` is too long or has a character not
+    /// usable in a label.
+    pub fn new(s: &str) -> Option<Self> {
+        let mut res = Self::default();
+        if s.len() > LABEL_LEN {
+            return None;
+        }
+        for (c, &b) in res.0.iter_mut().zip(s.as_bytes()) {
+            *c = LABEL_CHARS.iter().position(|&l| l == b)? as u8;
+        }
+        Some(res)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&c| c == 0)
+    }
+    pub fn char_at(&self, i: usize) -> char {
+        LABEL_CHARS[self.0[i] as usize] as char
+    }
+    pub fn next_char(&mut self, i: usize) {
+        self.0[i] = (self.0[i] + 1) % LABEL_CHARS.len() as u8;
+    }
+    pub fn prev_char(&mut self, i: usize) {
+        let len = LABEL_CHARS.len() as u8;
+        self.0[i] = (self.0[i] + len - 1) % len;
+    }
+    /// Replaces the characters from `len` by spaces.
+    pub fn truncate(&mut self, len: usize) {
+        for c in self.0.iter_mut().skip(len) {
+            *c = 0;
+        }
+    }
+    /// Encodes half of the label, `half` being 0 or 1.
+    fn as_u32(&self, half: usize) -> u32 {
+        self.0[half * 4..half * 4 + 4]
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (i, &c)| acc | u32::from(c) << (6 * i))
+    }
+    fn try_from(low: u32, high: u32) -> Option<Self> {
+        if low >> 24 != 0 || high >> 24 != 0 {
+            return None;
+        }
+        let mut res = Self::default();
+        for (i, c) in res.0.iter_mut().enumerate() {
+            let u = if i < 4 { low } else { high };
+            *c = (u >> (6 * (i % 4)) & 0x3f) as u8;
+        }
+        Some(res)
+    }
+}
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let len = self.0.iter().rposition(|&c| c != 0).map_or(0, |i| i + 1);
+        for i in 0..len {
+            write!(f, "{}", self.char_at(i))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Ringtone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Ringtone::*;
+        f.write_str(match self {
+            SoWhat => "So What",
+            MarioThemeIntro => "Mario",
+            ThirdKind => "Third Kind",
+            FrereJacques => "Frere Jacques",
+        })
+    }
 }
 
 bitflags! {
@@ -78,6 +1337,51 @@ impl Default for Mode {
     }
 }
 impl Mode {
+    fn weekdays() -> Self {
+        Mode::MONDAY | Mode::TUESDAY | Mode::WEDNESDAY | Mode::THURSDAY | Mode::FRIDAY
+    }
+    fn weekend() -> Self {
+        Mode::SATURDAY | Mode::SUNDAY
+    }
+    fn days() -> Self {
+        Self::all() - Mode::ONE_TIME
+    }
+    /// Parses a set of days as "Mo-Fr", "Sa,Su" or "Mo,We-Fr", a
+    /// range as "Sa-Mo" wrapping around the week. The keywords
+    /// "weekdays", "weekend", "daily" and "once" can be used as
+    /// items, and "never" alone.
+    pub fn parse(s: &str) -> Option<Self> {
+        if s == "never" {
+            return Some(Mode::empty());
+        }
+        let mut mode = Mode::empty();
+        for item in s.split(',') {
+            mode |= match item {
+                "once" => Mode::ONE_TIME,
+                "weekdays" => Mode::weekdays(),
+                "weekend" => Mode::weekend(),
+                "daily" => Mode::days(),
+                _ => {
+                    let mut bounds = item.splitn(2, '-');
+                    let first = parse_day(bounds.next()?)?;
+                    let last = match bounds.next() {
+                        Some(last) => parse_day(last)?,
+                        None => first,
+                    };
+                    let mut range = Mode::empty();
+                    let mut i = first;
+                    loop {
+                        range |= VEC_DAY_OF_WEEK_SHORT_NAME[i].0;
+                        if i == last {
+                            break range;
+                        }
+                        i = (i + 1) % 7;
+                    }
+                }
+            };
+        }
+        Some(mode)
+    }
     pub fn contains_dow(self, dow: DayOfWeek) -> bool {
         use self::DayOfWeek::*;
         match dow {
@@ -91,6 +1395,57 @@ impl Mode {
         }
     }
 }
+/// Formats compactly, parsed by `Mode::parse`. A one time mode is
+/// formatted as "once" followed by its days, as "once,Mo-Fr".
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.contains(Mode::ONE_TIME) {
+            f.write_str("once")?;
+            let days = *self - Mode::ONE_TIME;
+            if days.is_empty() {
+                return Ok(());
+            }
+            return write!(f, ",{}", days);
+        }
+        if self.is_empty() {
+            return f.write_str("never");
+        } else if *self == Mode::days() {
+            return f.write_str("daily");
+        } else if *self == Mode::weekdays() {
+            return f.write_str("weekdays");
+        } else if *self == Mode::weekend() {
+            return f.write_str("weekend");
+        }
+        let days = &VEC_DAY_OF_WEEK_SHORT_NAME;
+        let mut sep = "";
+        let mut i = 0;
+        while i < days.len() {
+            if !self.contains(days[i].0) {
+                i += 1;
+                continue;
+            }
+            let first = i;
+            while i + 1 < days.len() && self.contains(days[i + 1].0) {
+                i += 1;
+            }
+            let name = |i: usize| days[i].1.trim_start();
+            match i - first {
+                0 => write!(f, "{}{}", sep, name(first))?,
+                1 => write!(f, "{}{},{}", sep, name(first), name(i))?,
+                _ => write!(f, "{}{}-{}", sep, name(first), name(i))?,
+            }
+            sep = ",";
+            i += 1;
+        }
+        Ok(())
+    }
+}
+
+fn parse_day(s: &str) -> Option<usize> {
+    VEC_DAY_OF_WEEK_SHORT_NAME
+        .iter()
+        .position(|&(_, name)| name.trim_start() == s)
+}
 
 impl Default for Alarm {
     fn default() -> Self {
@@ -99,6 +1454,16 @@ impl Default for Alarm {
             hour: 12,
             min: 0,
             mode: Mode::default(),
+            date: None,
+            ringtone: Ringtone::default(),
+            ring_policy: RingPolicy::default(),
+            label: Label::default(),
+            skip_next: false,
+            recurrence: None,
+            rotation: None,
+            pre_alarm: 0,
+            holidays: None,
+            solar: None,
         }
     }
 }
@@ -117,61 +1482,432 @@ impl Alarm {
         assert!(m < 60);
         self.min = m;
     }
-    pub fn must_ring(&mut self, datetime: &DateTime) -> bool {
-        if !self.is_enable {
+    pub fn date(&self) -> Option<Date> {
+        self.date
+    }
+    /// Sets the date of the alarm. A dated alarm always rings only
+    /// one time.
+    pub fn set_date(&mut self, date: Option<Date>) {
+        if let Some(date) = date {
+            assert!(date.is_valid() && date.year >= 2000 && date.year < 2128);
+            self.mode = Mode::ONE_TIME;
+            self.recurrence = None;
+            self.rotation = None;
+        }
+        self.date = date;
+    }
+    pub fn recurrence(&self) -> Option<Recurrence> {
+        self.recurrence
+    }
+    /// Sets the recurrence rule of the alarm, that is then repeating.
+    pub fn set_recurrence(&mut self, recurrence: Option<Recurrence>) {
+        if let Some(recurrence) = recurrence {
+            assert!(recurrence.is_valid());
+            self.mode.remove(Mode::ONE_TIME);
+            self.date = None;
+            self.rotation = None;
+        }
+        self.recurrence = recurrence;
+    }
+    pub fn rotation(&self) -> Option<Rotation> {
+        self.rotation
+    }
+    /// Sets the shift work rotation of the alarm, giving its ring
+    /// times instead of the hour and minute of the alarm.
+    pub fn set_rotation(&mut self, rotation: Option<Rotation>) {
+        if let Some(rotation) = rotation {
+            assert!(rotation.is_valid());
+            self.mode.remove(Mode::ONE_TIME);
+            self.date = None;
+            self.recurrence = None;
+            self.solar = None;
+        }
+        self.rotation = rotation;
+    }
+    pub fn solar(&self) -> Option<Solar> {
+        self.solar
+    }
+    /// Sets the ring time relative to the sunrise or the sunset,
+    /// instead of the hour and minute of the alarm.
+    pub fn set_solar(&mut self, solar: Option<Solar>) {
+        if let Some(solar) = solar {
+            assert!(solar.offset.abs() <= MAX_SOLAR_OFFSET);
+            self.rotation = None;
+        }
+        self.solar = solar;
+    }
+    /// The ring time of `date`, `None` if the alarm can't ring this
+    /// day.
+    fn time_on(&self, date: Date, location: Option<Location>) -> Option<(u8, u8)> {
+        match (self.rotation, self.solar) {
+            (Some(rotation), _) => rotation.time_on(date),
+            (None, Some(solar)) => solar.time_on(date, location?),
+            (None, None) => Some((self.hour, self.min)),
+        }
+    }
+    /// The first day from `from` where the repeating alarm rings.
+    fn next_day(&self, from: Date) -> Option<Date> {
+        if let Some(recurrence) = self.recurrence {
+            return recurrence.next_day(from, self.mode);
+        }
+        let mut date = from;
+        for _ in 0..7 {
+            if self.mode.contains_dow(date.day_of_week()) {
+                return Some(date);
+            }
+            date = date.next();
+        }
+        None
+    }
+    /// Returns true if the alarm would ring this day of a repetition.
+    fn rings_on(&self, date: Date, dow: DayOfWeek) -> bool {
+        match self.recurrence {
+            None => self.mode.contains_dow(dow),
+            Some(recurrence) => recurrence.contains(date, self.mode),
+        }
+    }
+    /// Returns true if the `Mode` gives the days of the week.
+    fn uses_mode(&self) -> bool {
+        if self.rotation.is_some() {
+            return false;
+        }
+        match self.recurrence {
+            None => true,
+            Some(recurrence) => recurrence.uses_mode(),
+        }
+    }
+    /// Returns true if a repeating alarm has no day to ring.
+    fn is_never(&self) -> bool {
+        self.mode.is_empty() && self.uses_mode()
+    }
+    pub fn must_ring(&mut self, datetime: &DateTime, location: Option<Location>) -> bool {
+        if !self.is_enable || datetime.sec != 0 {
             return false;
         }
-        if datetime.sec != 0 || datetime.hour != self.hour || datetime.min != self.min {
+        let now = (datetime.hour, datetime.min);
+        if self.time_on(datetime.date(), location) != Some(now) {
             return false;
         }
-        if self.mode.contains(Mode::ONE_TIME) {
+        if let Some(date) = self.date {
+            if date != datetime.date() {
+                return false;
+            }
+        }
+        let ring = if self.rotation.is_some() {
+            true
+        } else if self.mode.contains(Mode::ONE_TIME) {
             self.is_enable = false;
             true
         } else {
-            self.mode.contains_dow(datetime.day_of_week)
+            self.rings_on(datetime.date(), datetime.day_of_week)
+        };
+        if ring && self.skip_next {
+            self.skip_next = false;
+            return false;
+        }
+        ring
+    }
+    /// Parses the text syntax of `as_text`. The settings it doesn't
+    /// represent have their default value.
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut words = s.split_whitespace();
+        let mut alarm = Alarm::default();
+        let time = words.next()?;
+        if let Some(solar) = Solar::parse(time) {
+            alarm.set_solar(Some(solar));
+        } else {
+            let mut time = time.splitn(2, ':');
+            alarm.hour = time.next()?.parse().ok()?;
+            alarm.min = time.next()?.parse().ok()?;
+            if alarm.hour >= 24 || alarm.min >= 60 {
+                return None;
+            }
+        }
+        alarm.mode = Mode::parse(words.next()?)?;
+        let mut word = words.next()?;
+        if alarm.mode.contains(Mode::ONE_TIME) {
+            if let Some(date) = Date::parse(word) {
+                if date.year < 2000 || date.year >= 2128 {
+                    return None;
+                }
+                alarm.set_date(Some(date));
+                word = words.next()?;
+            }
+        }
+        alarm.is_enable = match word {
+            "on" => true,
+            "off" => false,
+            _ => return None,
+        };
+        for word in words {
+            let mut setting = word.splitn(2, '=');
+            let (key, value) = (setting.next()?, setting.next()?);
+            match key {
+                "label" => alarm.label = Label::new(value)?,
+                "ringtone" => alarm.ringtone = Ringtone::parse(value)?,
+                // a recurrence would remove `Mode::ONE_TIME`
+                "every" | "monthly" if !alarm.mode.contains(Mode::ONE_TIME) => {
+                    alarm.set_recurrence(Some(parse_recurrence(key, value)?))
+                }
+                _ => return None,
+            }
+        }
+        Some(alarm)
+    }
+    /// The alarm in a text syntax, as "07:25 Mo-Fr on" or
+    /// "06:30 once 2020-05-01 off", the time being "sunrise-30" or
+    /// "sunset+15" for a solar time, followed by its label, ringtone
+    /// and recurrence when set, as "07:25 Mo,We on label=Gym
+    /// ringtone=mario every=2w@2020-05-04". The ring policy, the
+    /// rotation, the pre-alarm and the holidays are not represented.
+    pub fn as_text(&self) -> AlarmText<'_> {
+        AlarmText(self)
+    }
+    /// Returns true if `other` rings at the same time the same days.
+    pub fn is_duplicate_of(&self, other: &Alarm) -> bool {
+        // only compares what decides the ring time, as in `time_on`
+        let same_time = match (self.rotation, other.rotation) {
+            (Some(r), Some(s)) => r == s,
+            (None, None) => match (self.solar, other.solar) {
+                (Some(s), Some(t)) => s == t,
+                (None, None) => self.hour == other.hour && self.min == other.min,
+                _ => false,
+            },
+            _ => false,
+        };
+        same_time
+            && self.mode == other.mode
+            && self.date == other.date
+            && self.recurrence == other.recurrence
+    }
+    /// Returns true if the alarm rings more than one time.
+    pub fn is_repeating(&self) -> bool {
+        !self.mode.contains(Mode::ONE_TIME)
+    }
+    pub fn next_ring(
+        &self,
+        datetime: &DateTime,
+        location: Option<Location>,
+    ) -> Option<(Date, u8, u8)> {
+        self.next_ring_except(datetime, location, |_| false)
+    }
+    /// The next ring, a repeating alarm not ringing the days where
+    /// `is_exception` returns true.
+    pub fn next_ring_except<F>(
+        &self,
+        datetime: &DateTime,
+        location: Option<Location>,
+        is_exception: F,
+    ) -> Option<(Date, u8, u8)>
+    where
+        F: Fn(Date) -> bool,
+    {
+        let mut skip = self.skip_next;
+        let mut next = self.next_occurrence(datetime, location)?;
+        loop {
+            let is_exception = self.is_repeating() && is_exception(next.0);
+            if !is_exception && !skip {
+                return Some(next);
+            }
+            if !self.is_repeating() {
+                return None;
+            }
+            skip &= is_exception;
+            let (date, hour, min) = next;
+            next = self.next_occurrence(&datetime_at(date, hour, min), location)?;
         }
     }
-    pub fn next_ring(&self, datetime: &DateTime) -> Option<(DayOfWeek, u8, u8)> {
-        if !self.is_enable || self.mode.is_empty() {
+    fn next_occurrence(
+        &self,
+        datetime: &DateTime,
+        location: Option<Location>,
+    ) -> Option<(Date, u8, u8)> {
+        if !self.is_enable || self.is_never() {
             return None;
         }
-        let mut day = if time(self.hour, self.min) <= time(datetime.hour, datetime.min) {
-            datetime.day_of_week.next()
-        } else {
-            datetime.day_of_week
+        // without a location, a solar time never rings
+        if self.rotation.is_none() && self.solar.is_some() && location.is_none() {
+            return None;
+        }
+        let today = datetime.date();
+        let now = time(datetime.hour, datetime.min);
+        // the ring of `date`, if any and after now
+        let ring_on = |date: Date| match self.time_on(date, location) {
+            Some((h, m)) if date != today || time(h, m) > now => Some((date, h, m)),
+            _ => None,
         };
+        if let Some(rotation) = self.rotation {
+            let mut date = core::cmp::max(today, rotation.start);
+            for _ in 0..=rotation.nb_days() {
+                if let Some(ring) = ring_on(date) {
+                    return Some(ring);
+                }
+                date = date.next();
+            }
+            return None;
+        }
+        if let Some(date) = self.date {
+            if date < today {
+                return None;
+            }
+            return ring_on(date);
+        }
         if self.mode.contains(Mode::ONE_TIME) {
-            return Some((day, self.hour, self.min));
+            return ring_on(today).or_else(|| ring_on(today.next()));
         }
+        let mut date = today;
         loop {
-            if self.mode.contains_dow(day) {
-                return Some((day, self.hour, self.min));
+            // the year limit of the dates stops the days never ringing
+            date = self.next_day(date).filter(|d| d.year < 2128)?;
+            if let Some(ring) = ring_on(date) {
+                return Some(ring);
             }
-            day = day.next();
+            date = date.next();
         }
     }
+    /// Encodes the alarm, the date excepted, in an `u32`.
     pub fn as_u32(&self) -> u32 {
         self.mode.bits() as u32
             | (self.min as u32) << 8
             | (self.hour as u32) << 16
+            | (self.skip_next as u32) << 23
             | (self.is_enable as u32) << 24
-            | 1 << 25
+            | (self.ringtone as u32) << 25
+            | 3 << 28
     }
     pub fn try_from(u: u32) -> Option<Self> {
-        if u >> 25 != 1 {
+        let res = if u >> 25 == 1 {
+            Self {
+                is_enable: (u & 1 << 24) != 0,
+                hour: (u >> 16) as u8,
+                min: (u >> 8) as u8,
+                mode: Mode::from_bits_truncate(u as u8),
+                date: None,
+                ringtone: Ringtone::default(),
+                ring_policy: RingPolicy::default(),
+                label: Label::default(),
+                skip_next: false,
+                recurrence: None,
+                rotation: None,
+                pre_alarm: 0,
+                holidays: None,
+                solar: None,
+            }
+        } else if u >> 29 == 2 {
+            let date = Date {
+                year: 2000 + (u >> 9 & 0x7f) as u16,
+                month: (u >> 5 & 0xf) as u8,
+                day: (u & 0x1f) as u8,
+            };
+            if !date.is_valid() || u & 1 << 16 != 0 {
+                return None;
+            }
+            Self {
+                is_enable: (u & 1 << 28) != 0,
+                hour: (u >> 23 & 0x1f) as u8,
+                min: (u >> 17 & 0x3f) as u8,
+                mode: Mode::ONE_TIME,
+                date: Some(date),
+                ringtone: Ringtone::default(),
+                ring_policy: RingPolicy::default(),
+                label: Label::default(),
+                skip_next: false,
+                recurrence: None,
+                rotation: None,
+                pre_alarm: 0,
+                holidays: None,
+                solar: None,
+            }
+        } else if u >> 28 == 3 {
+            Self {
+                is_enable: (u & 1 << 24) != 0,
+                hour: (u >> 16 & 0x7f) as u8,
+                min: (u >> 8) as u8,
+                mode: Mode::from_bits_truncate(u as u8),
+                date: None,
+                ringtone: Ringtone::from_u8((u >> 25 & 0x7) as u8)?,
+                ring_policy: RingPolicy::default(),
+                label: Label::default(),
+                skip_next: (u & 1 << 23) != 0,
+                recurrence: None,
+                rotation: None,
+                pre_alarm: 0,
+                holidays: None,
+                solar: None,
+            }
+        } else {
             return None;
-        }
-        let res = Self {
-            is_enable: (u & 1 << 24) != 0,
-            hour: (u >> 16) as u8,
-            min: (u >> 8) as u8,
-            mode: Mode::from_bits_truncate(u as u8),
         };
         if res.hour > 23 || res.min > 59 {
             return None;
         }
         Some(res)
     }
+    pub fn as_words(&self) -> [u32; ALARM_WORDS] {
+        let date = self.date.and_then(|d| d.to_days()).unwrap_or(0);
+        let rotation = self.rotation.map_or([0; ROTATION_WORDS], |r| r.as_words());
+        [
+            self.as_u32(),
+            date,
+            self.ring_policy.as_u32(),
+            self.label.as_u32(0),
+            self.label.as_u32(1),
+            self.recurrence.map_or(0, |r| r.as_u32()),
+            rotation[0],
+            rotation[1],
+            rotation[2],
+            rotation[3],
+            u32::from(self.pre_alarm)
+                | u32::from(self.holidays.map_or(0, Country::as_u8)) << 8
+                | self.solar.map_or(0, |s| s.as_u32()) << 20,
+        ]
+    }
+    /// Decodes an alarm, the missing words getting their default
+    /// values.
+    pub fn from_words(words: &[Option<u32>; ALARM_WORDS]) -> Option<Self> {
+        let mut res = Self::try_from(words[0]?)?;
+        match words[1] {
+            None | Some(0) => {}
+            Some(days) => {
+                let date = Date::new(days);
+                if date.year < 2000 || date.year > 2127 {
+                    return None;
+                }
+                res.set_date(Some(date));
+            }
+        }
+        if let Some(policy) = words[2] {
+            res.ring_policy = RingPolicy::try_from(policy)?;
+        }
+        // a short label has no high word
+        res.label = Label::try_from(words[3].unwrap_or(0), words[4].unwrap_or(0))?;
+        match words[5] {
+            None | Some(0) => {}
+            Some(u) => res.set_recurrence(Some(Recurrence::try_from(u)?)),
+        }
+        match words[6] {
+            None | Some(0) => {}
+            Some(start) => {
+                let words = [
+                    start,
+                    words[7].unwrap_or(0),
+                    words[8].unwrap_or(0),
+                    words[9].unwrap_or(0),
+                ];
+                res.set_rotation(Some(Rotation::from_words(&words)?));
+            }
+        }
+        if let Some(word) = words[10] {
+            let pre_alarm = (word & 0xff) as u8;
+            if pre_alarm > MAX_PRE_ALARM || word >> 16 & 0xf != 0 {
+                return None;
+            }
+            res.pre_alarm = pre_alarm;
+            res.holidays = Country::from_u8((word >> 8) as u8)?;
+            res.solar = Solar::from_u32(word >> 20)?;
+        }
+        Some(res)
+    }
 }
 impl fmt::Display for Alarm {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -180,15 +1916,33 @@ impl fmt::Display for Alarm {
         } else {
             f.write_str("Off ")?;
         }
-        write!(f, "{:02}:{:02}", self.hour, self.min)?;
-        if self.mode.contains(Mode::ONE_TIME) {
+        if self.rotation.is_some() {
+            f.write_str("rotation")?;
+        } else if let Some(solar) = self.solar {
+            write!(f, "{}", solar)?;
+        } else {
+            write!(f, "{:02}:{:02}", self.hour, self.min)?;
+        }
+        if !self.label.is_empty() {
+            write!(f, " {}", self.label)?;
+        }
+        if let Some(rotation) = self.rotation {
+            write!(f, " {}", rotation)?;
+        } else if let Some(date) = self.date {
+            write!(f, " {}", date)?;
+        } else if self.mode.contains(Mode::ONE_TIME) {
             f.write_str(" one time")?;
-        } else if self.mode.is_empty() {
+        } else if self.is_never() {
             f.write_str(" never")?;
         } else {
-            for &(dow, s) in VEC_DAY_OF_WEEK_SHORT_NAME.iter() {
-                if self.mode.contains(dow) {
-                    f.write_str(s)?;
+            if let Some(recurrence) = self.recurrence {
+                write!(f, " {}", recurrence)?;
+            }
+            if self.uses_mode() {
+                for &(dow, s) in VEC_DAY_OF_WEEK_SHORT_NAME.iter() {
+                    if self.mode.contains(dow) {
+                        f.write_str(s)?;
+                    }
                 }
             }
         }
@@ -196,6 +1950,76 @@ impl fmt::Display for Alarm {
     }
 }
 
+/// Formats an alarm in its text syntax, see `Alarm::as_text`.
+pub struct AlarmText<'a>(&'a Alarm);
+impl<'a> fmt::Display for AlarmText<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let alarm = self.0;
+        match alarm.solar {
+            Some(solar) => write!(f, "{}", solar)?,
+            None => write!(f, "{:02}:{:02}", alarm.hour, alarm.min)?,
+        }
+        write!(f, " {}", alarm.mode)?;
+        if let Some(date) = alarm.date {
+            write!(f, " {}", date)?;
+        }
+        f.write_str(if alarm.is_enable { " on" } else { " off" })?;
+        if !alarm.label.is_empty() {
+            write!(f, " label={}", alarm.label)?;
+        }
+        if alarm.ringtone != Ringtone::default() {
+            write!(f, " ringtone={}", alarm.ringtone.as_word())?;
+        }
+        match alarm.recurrence {
+            None => Ok(()),
+            Some(Recurrence::Daily { interval, start }) => {
+                write!(f, " every={}d@{}", interval, start)
+            }
+            Some(Recurrence::Weekly { interval, start }) => {
+                write!(f, " every={}w@{}", interval, start)
+            }
+            Some(Recurrence::MonthDay(n)) => write!(f, " monthly={}", n),
+            Some(Recurrence::MonthWeekDay(n, dow)) => {
+                let dow = VEC_DAY_OF_WEEK_SHORT_NAME[dow as usize].1;
+                write!(f, " monthly={}{}", n, dow.trim_start())
+            }
+        }
+    }
+}
+
+/// Parses the recurrence of the text syntax of an alarm, as
+/// "every=3d@2020-05-01", "every=2w@2020-05-04", "monthly=-1" or
+/// "monthly=2Tu".
+fn parse_recurrence(key: &str, value: &str) -> Option<Recurrence> {
+    let res = if key == "every" {
+        let mut parts = value.splitn(2, '@');
+        let interval = parts.next()?;
+        let start = Date::parse(parts.next()?)?;
+        let i = interval.len().checked_sub(1)?;
+        let unit = interval.get(i..)?;
+        let interval = interval.get(..i)?.parse().ok()?;
+        match unit {
+            "d" => Recurrence::Daily { interval, start },
+            "w" => Recurrence::Weekly { interval, start },
+            _ => return None,
+        }
+    } else {
+        let day = value.len().checked_sub(2).and_then(|i| {
+            let dow = dow_from_u8(parse_day(value.get(i..)?)? as u8)?;
+            Some((i, dow))
+        });
+        match day {
+            Some((i, dow)) => Recurrence::MonthWeekDay(value[..i].parse().ok()?, dow),
+            None => Recurrence::MonthDay(value.parse().ok()?),
+        }
+    };
+    if res.is_valid() {
+        Some(res)
+    } else {
+        None
+    }
+}
+
 static VEC_DAY_OF_WEEK_SHORT_NAME: [(Mode, &str); 7] = [
     (Mode::MONDAY, " Mo"),
     (Mode::TUESDAY, " Tu"),
@@ -205,13 +2029,60 @@ static VEC_DAY_OF_WEEK_SHORT_NAME: [(Mode, &str); 7] = [
     (Mode::SATURDAY, " Sa"),
     (Mode::SUNDAY, " Su"),
 ];
+fn datetime_at(date: Date, hour: u8, min: u8) -> DateTime {
+    DateTime {
+        year: date.year,
+        month: date.month,
+        day: date.day,
+        hour,
+        min,
+        sec: 0,
+        day_of_week: date.day_of_week(),
+    }
+}
+
 fn time(hour: u8, min: u8) -> u32 {
     u32::from(hour) * 60 + u32::from(min)
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chime::ChimeKind;
+    use crate::store::{Error, RamFlash};
+    use std::string::ToString;
+
+    fn date(year: u16, month: u8, day: u8) -> Date {
+        Date { year, month, day }
+    }
+
+    /// The words of an alarm saved by a version using only the `n`
+    /// first words.
+    fn first_words(alarm: &Alarm, n: usize) -> [Option<u32>; ALARM_WORDS] {
+        let mut words = [None; ALARM_WORDS];
+        for (w, &u) in words.iter_mut().zip(alarm.as_words().iter()).take(n) {
+            *w = Some(u);
+        }
+        words
+    }
+
+    fn next_ring(alarm_manager: &AlarmManager, dt: &DateTime) -> Option<(Date, u8, u8)> {
+        alarm_manager
+            .next_ring(dt)
+            .map(|r| (r.datetime.date(), r.datetime.hour, r.datetime.min))
+    }
+
+    fn at(year: u16, month: u8, day: u8, hour: u8, min: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            min,
+            sec: 0,
+            day_of_week: date(year, month, day).day_of_week(),
+        }
+    }
 
     fn default_alarm_manager() -> AlarmManager {
         let mut alarm_manager = AlarmManager::default();
@@ -240,8 +2111,8 @@ mod test {
             day_of_week: DayOfWeek::Wednesday,
         };
 
-        let next = alarm_manager.next_ring(&datetime);
-        assert_eq!(next, Some((DayOfWeek::Thursday, 7, 25)));
+        let next = next_ring(&alarm_manager, &datetime);
+        assert_eq!(next, Some((date(2018, 10, 18), 7, 25)));
     }
 
     #[test]
@@ -262,8 +2133,8 @@ mod test {
             day_of_week: DayOfWeek::Saturday,
         };
 
-        let next = alarm_manager.next_ring(&datetime);
-        assert_eq!(next, Some((DayOfWeek::Saturday, 17, 30)));
+        let next = next_ring(&alarm_manager, &datetime);
+        assert_eq!(next, Some((date(2018, 12, 1), 17, 30)));
     }
 
     #[test]
@@ -284,8 +2155,8 @@ mod test {
             day_of_week: DayOfWeek::Saturday,
         };
 
-        let next = alarm_manager.next_ring(&datetime);
-        assert_eq!(next, Some((DayOfWeek::Monday, 7, 25)));
+        let next = next_ring(&alarm_manager, &datetime);
+        assert_eq!(next, Some((date(2018, 12, 3), 7, 25)));
     }
 
     #[test]
@@ -294,10 +2165,1141 @@ mod test {
         let mut alarm = Alarm::default();
         assert_eq!(Some(alarm.clone()), Alarm::try_from(alarm.as_u32()));
         assert_eq!(None, Alarm::try_from(alarm.as_u32() | 60 << 8));
-        assert_eq!(None, Alarm::try_from(alarm.as_u32() | 1 << 26));
+        assert_eq!(None, Alarm::try_from(alarm.as_u32() | 4 << 25));
         alarm.set_hour(0);
         assert_eq!(None, Alarm::try_from(alarm.as_u32() | 24 << 16));
         alarm.is_enable = true;
         assert_eq!(Some(alarm.clone()), Alarm::try_from(alarm.as_u32()));
     }
+
+    #[test]
+    fn test_dated_alarm() {
+        let mut alarm_manager = default_alarm_manager();
+        alarm_manager.alarms[2].is_enable = true;
+        alarm_manager.alarms[2].set_hour(7);
+        alarm_manager.alarms[2].set_min(0);
+        alarm_manager.alarms[2].set_date(Some(date(2018, 10, 25)));
+        assert_eq!(alarm_manager.alarms[2].mode, Mode::ONE_TIME);
+
+        let mut datetime = DateTime {
+            year: 2018,
+            month: 10,
+            day: 18,
+            hour: 7,
+            min: 0,
+            sec: 0,
+            day_of_week: DayOfWeek::Thursday,
+        };
+        assert!(!alarm_manager.alarms[2].must_ring(&datetime, None));
+        assert_eq!(
+            alarm_manager.alarms[2].next_ring(&datetime, None),
+            Some((date(2018, 10, 25), 7, 0))
+        );
+
+        datetime.day = 25;
+        assert_eq!(
+            next_ring(&alarm_manager, &datetime),
+            Some((date(2018, 10, 25), 7, 25))
+        );
+        assert!(alarm_manager.must_ring(&datetime).is_some());
+        assert!(!alarm_manager.alarms[2].is_enable);
+    }
+
+    #[test]
+    fn test_next_ring_far_date() {
+        let mut alarm_manager: AlarmManager = AlarmManager::default();
+        alarm_manager.alarms[3].is_enable = true;
+        alarm_manager.alarms[3].set_date(Some(date(2026, 12, 24)));
+
+        let datetime = DateTime::new(1_539_588_300);
+        assert_eq!(
+            next_ring(&alarm_manager, &datetime),
+            Some((date(2026, 12, 24), 12, 0))
+        );
+
+        let datetime = DateTime {
+            year: 2026,
+            month: 12,
+            day: 24,
+            hour: 12,
+            min: 0,
+            sec: 30,
+            day_of_week: DayOfWeek::Thursday,
+        };
+        assert_eq!(next_ring(&alarm_manager, &datetime), None);
+    }
+
+    #[test]
+    fn test_dated_alarm_from_words() {
+        let mut alarm = Alarm::default();
+        alarm.set_hour(23);
+        alarm.set_min(59);
+        alarm.set_date(Some(date(2127, 12, 31)));
+        let mut words = first_words(&alarm, 2);
+        assert_eq!(Some(alarm.clone()), Alarm::from_words(&words));
+        alarm.set_date(None);
+        assert_eq!(
+            Some(alarm.clone()),
+            Alarm::from_words(&first_words(&alarm, 1))
+        );
+        words[0] = None;
+        assert_eq!(None, Alarm::from_words(&words));
+
+        // version 2 encoding
+        alarm.is_enable = true;
+        alarm.set_date(Some(date(2000, 2, 29)));
+        let v2 = 29 | 2 << 5 | 59 << 17 | 23 << 23 | 1 << 28 | 2 << 29;
+        assert_eq!(Some(alarm.clone()), Alarm::try_from(v2));
+        assert_eq!(None, Alarm::try_from(v2 | 31));
+        assert_eq!(None, Alarm::try_from(v2 | 1 << 16));
+        assert_eq!(None, Alarm::try_from(v2 | 1 << 31));
+    }
+
+    #[test]
+    fn test_ringtone() {
+        let alarm = Alarm {
+            ringtone: Ringtone::MarioThemeIntro,
+            ..Alarm::default()
+        };
+        assert_eq!(Some(alarm.clone()), Alarm::try_from(alarm.as_u32()));
+        assert_eq!(None, Alarm::try_from(alarm.as_u32() | 7 << 25));
+
+        let mut ringtone = Ringtone::default();
+        for _ in 0..Ringtone::NB {
+            assert_eq!(ringtone.next().prev(), ringtone);
+            ringtone = ringtone.next();
+        }
+        assert_eq!(ringtone, Ringtone::default());
+        assert_eq!(Ringtone::default().prev(), Ringtone::FrereJacques);
+
+        // version 1 encoding
+        let v1 = Mode::MONDAY.bits() as u32 | 30 << 8 | 6 << 16 | 1 << 25;
+        let alarm = Alarm::try_from(v1).unwrap();
+        assert_eq!(alarm.ringtone, Ringtone::SoWhat);
+        assert_eq!((alarm.hour(), alarm.min()), (6, 30));
+
+        let mut alarm_manager = default_alarm_manager();
+        alarm_manager.alarms[1].ringtone = Ringtone::MarioThemeIntro;
+        let datetime = DateTime {
+            year: 2018,
+            month: 10,
+            day: 17,
+            hour: 8,
+            min: 15,
+            sec: 0,
+            day_of_week: DayOfWeek::Wednesday,
+        };
+        assert!(alarm_manager.must_ring(&datetime).is_some());
+        assert_eq!(alarm_manager.ringtone(), Ringtone::MarioThemeIntro);
+    }
+
+    #[test]
+    fn test_ring_policy() {
+        let policy = RingPolicy {
+            duration: 15,
+            escalate_after: 5,
+            escalation: Ringtone::MarioThemeIntro,
+            challenge: Challenge::Arithmetic,
+        };
+        assert_eq!(Some(policy), RingPolicy::try_from(policy.as_u32()));
+        assert_eq!(
+            Some(Challenge::Press),
+            RingPolicy::try_from(0x0101_000a).map(|p| p.challenge)
+        );
+        assert_eq!(None, RingPolicy::try_from(policy.as_u32() & !0xff));
+        assert_eq!(None, RingPolicy::try_from(0));
+
+        let ringtone = Ringtone::SoWhat;
+        assert_eq!(policy.ringtone_at(ringtone, 0), Some(Ringtone::SoWhat));
+        assert_eq!(policy.ringtone_at(ringtone, 299), Some(Ringtone::SoWhat));
+        assert_eq!(
+            policy.ringtone_at(ringtone, 300),
+            Some(Ringtone::MarioThemeIntro)
+        );
+        assert_eq!(
+            policy.ringtone_at(ringtone, 899),
+            Some(Ringtone::MarioThemeIntro)
+        );
+        assert_eq!(policy.ringtone_at(ringtone, 900), None);
+        assert_eq!(
+            RingPolicy::default().ringtone_at(ringtone, 599),
+            Some(ringtone)
+        );
+
+        let alarm = Alarm {
+            ring_policy: policy,
+            ..Alarm::default()
+        };
+        assert_eq!(
+            Some(alarm.clone()),
+            Alarm::from_words(&first_words(&alarm, 3))
+        );
+    }
+
+    #[test]
+    fn test_missed() {
+        let mut alarm_manager = default_alarm_manager();
+        alarm_manager.alarms[0].ring_policy.duration = 2;
+        let epoch = 1_539_588_300;
+        let rings = alarm_manager.must_ring(&DateTime::new(epoch)).unwrap();
+        assert_eq!(rings.alarms().collect::<Vec<_, U4>>(), &[0]);
+        assert!(rings.contains(0) && !rings.nap);
+        assert!(!alarm_manager.check_missed(&DateTime::new(epoch + 119)));
+        assert!(alarm_manager.check_missed(&DateTime::new(epoch + 120)));
+        assert!(!alarm_manager.check_missed(&DateTime::new(epoch + 121)));
+        assert_eq!(alarm_manager.missed(), Some(DateTime::new(epoch)));
+        alarm_manager.acknowledge_missed();
+        assert_eq!(alarm_manager.missed(), None);
+
+        assert!(alarm_manager
+            .must_ring(&DateTime::new(epoch + 86400))
+            .is_some());
+        alarm_manager.dismiss();
+        assert!(!alarm_manager.check_missed(&DateTime::new(epoch + 86400 + 120)));
+
+        assert!(alarm_manager
+            .must_ring(&DateTime::new(epoch + 3 * 86400))
+            .is_some());
+        assert!(alarm_manager.snooze(&DateTime::new(epoch + 3 * 86400 + 60)));
+        assert!(!alarm_manager.check_missed(&DateTime::new(epoch + 3 * 86400 + 120)));
+        assert_eq!(alarm_manager.missed(), None);
+    }
+
+    #[test]
+    fn test_label() {
+        assert_eq!(Label::new("Work-42z").unwrap().to_string(), "Work-42z");
+        assert_eq!(Label::new("Too long!"), None);
+        assert_eq!(Label::new("Gym!"), None);
+        assert!(Label::new("").unwrap().is_empty());
+
+        let mut label = Label::new("Gym").unwrap();
+        label.prev_char(1);
+        label.next_char(3);
+        assert_eq!(label.to_string(), "GxmA");
+        label.truncate(2);
+        assert_eq!(label.to_string(), "Gx");
+        label.prev_char(0);
+        label.prev_char(0);
+        label.next_char(1);
+        assert_eq!(label.to_string(), "Ey");
+        assert_eq!(label.char_at(7), ' ');
+
+        let mut alarm_manager = default_alarm_manager();
+        alarm_manager.alarms[0].label = Label::new("Work").unwrap();
+        let alarm = &alarm_manager.alarms[0];
+        assert_eq!(alarm.to_string(), "On  07:25 Work Mo Tu Th Fr");
+        let mut words = first_words(alarm, ALARM_WORDS);
+        assert_eq!(Some(alarm.clone()), Alarm::from_words(&words));
+        words[4] = Some(1 << 24);
+        assert_eq!(None, Alarm::from_words(&words));
+
+        // tuesday after the alarm, next is wednesday 8:15
+        let datetime = DateTime::new(1_539_588_300 + 86400);
+        let next = alarm_manager.next_ring(&datetime).unwrap();
+        assert_eq!(next.alarm, Some(1));
+        assert_eq!(alarm_manager.label_of(&next), Label::default());
+        let datetime = DateTime::new(1_539_588_300 - 60);
+        let next = alarm_manager.next_ring(&datetime).unwrap();
+        assert_eq!(next.alarm, Some(0));
+        assert_eq!(alarm_manager.label_of(&next), Label::new("Work").unwrap());
+        assert!(alarm_manager
+            .must_ring(&DateTime::new(1_539_588_300))
+            .is_some());
+        assert_eq!(alarm_manager.label(), Label::new("Work").unwrap());
+    }
+
+    #[test]
+    fn test_store() {
+        let mut alarm_manager = default_alarm_manager();
+        alarm_manager.alarms[0].label = Label::new("Gym").unwrap();
+        alarm_manager.alarms[1].label = Label::new("Work-42z").unwrap();
+        alarm_manager.alarms[2].label = Label::new("Work").unwrap();
+        // the 2 banks of 2 pages of the firmware
+        let mut store = Store::new(RamFlash::new(1024));
+        assert_eq!(store.save(alarm_manager.records()), Ok(()));
+        let loaded: AlarmManager = AlarmManager::load(&store);
+        assert_eq!(loaded.alarms, alarm_manager.alarms);
+
+        // every record fits, even after a compaction
+        assert!(alarm_manager.records().count() <= store.capacity());
+        for alarm in alarm_manager.alarms.iter_mut() {
+            alarm.label = Label::new("Work-42z").unwrap();
+        }
+        let exception = Exception::new(date(2018, 10, 15), date(2018, 10, 20));
+        while alarm_manager.exceptions.push(exception).is_ok() {}
+        for name in alarm_manager.profiles.iter_mut() {
+            *name = Label::new("Holidays").unwrap();
+        }
+        alarm_manager.location = Some(Location {
+            latitude: 4886,
+            longitude: 235,
+            utc_offset: 120,
+        });
+        for hour in 0..4 {
+            for alarm in alarm_manager.alarms.iter_mut() {
+                alarm.hour = hour;
+            }
+            assert_eq!(store.save(alarm_manager.records()), Ok(()));
+        }
+        let loaded: AlarmManager = AlarmManager::load(&store);
+        assert_eq!(loaded.alarms, alarm_manager.alarms);
+        assert_eq!(loaded.exceptions, alarm_manager.exceptions);
+        assert_eq!(loaded.profiles, alarm_manager.profiles);
+        assert_eq!(loaded.location, alarm_manager.location);
+
+        // 3 full profiles fit, not 4, the saved ones being kept
+        let first = alarm_manager.clone();
+        for p in 1..NB_PROFILES as u8 {
+            alarm_manager.activate_profile(AlarmManager::load_profile(&store, p));
+            while alarm_manager.exceptions.push(exception).is_ok() {}
+            for alarm in alarm_manager.alarms.iter_mut() {
+                alarm.label = Label::new("Work-42z").unwrap();
+                alarm.hour = p;
+            }
+            let res = store.save(alarm_manager.records());
+            assert_eq!(res.err(), Some(Error::Full).filter(|_| p == 3));
+        }
+        let loaded: AlarmManager = AlarmManager::load_profile(&store, 0);
+        assert_eq!(loaded.alarms, first.alarms);
+        assert_eq!(loaded.exceptions, first.exceptions);
+        let loaded: AlarmManager = AlarmManager::load_profile(&store, 2);
+        assert_eq!(loaded.alarms[0].hour, 2);
+    }
+
+    #[test]
+    fn test_skip_next() {
+        let mut alarm_manager = default_alarm_manager();
+        alarm_manager.alarms[0].skip_next = true;
+        let alarm = &alarm_manager.alarms[0];
+        assert_eq!(Some(alarm.clone()), Alarm::try_from(alarm.as_u32()));
+
+        // monday 7:00, the alarm of 7:25 is skipped
+        let datetime = DateTime::new(1_539_588_300 - 25 * 60);
+        assert_eq!(
+            next_ring(&alarm_manager, &datetime),
+            Some((date(2018, 10, 16), 7, 25))
+        );
+        assert!(alarm_manager
+            .must_ring(&DateTime::new(1_539_588_300))
+            .is_none());
+        assert!(!alarm_manager.alarms[0].skip_next);
+        assert!(alarm_manager
+            .must_ring(&DateTime::new(1_539_588_300 + 86400))
+            .is_some());
+
+        alarm_manager.alarms[2].is_enable = true;
+        alarm_manager.alarms[2].mode = Mode::ONE_TIME;
+        alarm_manager.alarms[2].skip_next = true;
+        assert_eq!(alarm_manager.alarms[2].next_ring(&datetime, None), None);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let alarm_manager = AlarmManager::<U2>::default();
+        assert_eq!(alarm_manager.alarms.len(), 2);
+        assert_eq!(default_alarm_manager().alarms.len(), 20);
+        assert_eq!(AlarmManager::<U32>::default().alarms.len(), MAX_ALARMS);
+
+        // the last alarm of a full manager rings, monday 7:25
+        let mut alarm_manager = AlarmManager::<U32>::default();
+        alarm_manager.alarms[31] = default_alarm_manager().alarms[0].clone();
+        let rings = alarm_manager
+            .must_ring(&DateTime::new(1_539_588_300))
+            .unwrap();
+        assert_eq!(rings.alarms().collect::<Vec<_, U4>>(), &[31]);
+        assert!(rings.contains(31) && !rings.contains(32));
+    }
+
+    #[test]
+    #[should_panic(expected = "too many alarms")]
+    fn test_too_many_alarms() {
+        AlarmManager::<U33>::default();
+    }
+
+    #[test]
+    fn test_snooze() {
+        let mut alarm_manager = default_alarm_manager();
+        let datetime = DateTime {
+            year: 2018,
+            month: 10,
+            day: 15,
+            hour: 7,
+            min: 25,
+            sec: 0,
+            day_of_week: DayOfWeek::Monday,
+        };
+        let epoch = datetime.to_epoch().unwrap();
+        assert!(alarm_manager.must_ring(&datetime).is_some());
+        assert_eq!(alarm_manager.remaining_snoozes(), 3);
+
+        let pressed = DateTime::new(epoch + 10);
+        assert!(alarm_manager.snooze(&pressed));
+        assert_eq!(alarm_manager.remaining_snoozes(), 2);
+        assert_eq!(
+            alarm_manager.snoozed_until(),
+            Some(DateTime::new(epoch + 10 + 9 * 60))
+        );
+        assert_eq!(
+            next_ring(&alarm_manager, &pressed),
+            Some((date(2018, 10, 15), 7, 34))
+        );
+        assert!(alarm_manager
+            .must_ring(&DateTime::new(epoch + 9 * 60 + 9))
+            .is_none());
+        assert!(alarm_manager
+            .must_ring(&DateTime::new(epoch + 9 * 60 + 10))
+            .is_some());
+        assert_eq!(alarm_manager.snoozed_until(), None);
+        assert_eq!(
+            next_ring(&alarm_manager, &DateTime::new(epoch + 9 * 60 + 10)),
+            Some((date(2018, 10, 16), 7, 25))
+        );
+
+        assert!(alarm_manager.snooze(&DateTime::new(epoch + 10 * 60)));
+        assert!(alarm_manager.snooze(&DateTime::new(epoch + 20 * 60)));
+        assert_eq!(alarm_manager.remaining_snoozes(), 0);
+        assert!(!alarm_manager.snooze(&DateTime::new(epoch + 30 * 60)));
+        assert_eq!(alarm_manager.snoozed_until(), None);
+        assert_eq!(alarm_manager.remaining_snoozes(), 3);
+    }
+
+    #[test]
+    fn test_snooze_shortening() {
+        let config = SnoozeConfig {
+            duration: 10,
+            max_snoozes: 5,
+            shortening: 3,
+        };
+        assert_eq!(config.duration(0), 10);
+        assert_eq!(config.duration(1), 7);
+        assert_eq!(config.duration(3), 1);
+        assert_eq!(config.duration(200), 1);
+
+        let mut alarm_manager = default_alarm_manager();
+        alarm_manager.snooze_config = config;
+        let datetime = DateTime::new(1_539_588_300);
+        assert!(alarm_manager.snooze(&datetime));
+        assert!(alarm_manager.snooze(&datetime));
+        assert_eq!(
+            alarm_manager.snoozed_until(),
+            Some(DateTime::new(1_539_588_300 + 7 * 60))
+        );
+    }
+
+    #[test]
+    fn test_next_ring_before_snooze() {
+        let mut alarm_manager = default_alarm_manager();
+        alarm_manager.alarms[2].is_enable = true;
+        alarm_manager.alarms[2].set_hour(7);
+        alarm_manager.alarms[2].set_min(30);
+        alarm_manager.alarms[2].mode = Mode::MONDAY;
+
+        let datetime = DateTime {
+            year: 2018,
+            month: 10,
+            day: 15,
+            hour: 7,
+            min: 25,
+            sec: 0,
+            day_of_week: DayOfWeek::Monday,
+        };
+        assert!(alarm_manager.snooze(&datetime));
+        assert_eq!(
+            next_ring(&alarm_manager, &datetime),
+            Some((date(2018, 10, 15), 7, 30))
+        );
+    }
+
+    #[test]
+    fn test_exceptions() {
+        let mut alarm_manager = default_alarm_manager();
+        // no alarm 0 from monday to tuesday, no alarm at all on wednesday
+        let mut exception = Exception::new(date(2018, 10, 15), date(2018, 10, 16));
+        exception.alarms = 1 << 0;
+        alarm_manager.exceptions.push(exception).unwrap();
+        let exception = Exception::new(date(2018, 10, 17), date(2018, 10, 17));
+        alarm_manager.exceptions.push(exception).unwrap();
+        alarm_manager.alarms[2].is_enable = true;
+        alarm_manager.alarms[2].set_date(Some(date(2018, 10, 17)));
+
+        let epoch = 1_539_588_300;
+        assert!(alarm_manager.must_ring(&DateTime::new(epoch)).is_none());
+        assert_eq!(
+            alarm_manager.alarms[0].next_ring(&DateTime::new(epoch - 60), None),
+            Some((date(2018, 10, 15), 7, 25))
+        );
+        assert_eq!(
+            next_ring(&alarm_manager, &DateTime::new(epoch - 60)),
+            Some((date(2018, 10, 17), 12, 0))
+        );
+        alarm_manager.alarms[2].is_enable = false;
+        assert_eq!(
+            next_ring(&alarm_manager, &DateTime::new(epoch - 60)),
+            Some((date(2018, 10, 18), 7, 25))
+        );
+        assert!(alarm_manager
+            .must_ring(&DateTime::new(epoch + 2 * 86400 + 50 * 60))
+            .is_none());
+        assert!(alarm_manager
+            .must_ring(&DateTime::new(epoch + 3 * 86400))
+            .is_some());
+
+        let words = alarm_manager.exceptions[0].as_words();
+        let words = [Some(words[0]), Some(words[1]), Some(words[2])];
+        assert_eq!(
+            Exception::from_words(&words),
+            Some(alarm_manager.exceptions[0])
+        );
+        assert_eq!(Exception::from_words(&[Some(0), Some(0), Some(0)]), None);
+
+        let last = alarm_manager.exceptions[1];
+        alarm_manager.remove_exception(0);
+        assert_eq!(&alarm_manager.exceptions[..], &[last]);
+        alarm_manager.remove_exception(1);
+        assert_eq!(alarm_manager.exceptions.len(), 1);
+    }
+
+    #[test]
+    fn test_recurrence() {
+        let mut alarm = Alarm {
+            is_enable: true,
+            ..Alarm::default()
+        };
+        alarm.set_hour(7);
+        alarm.set_min(0);
+        let next = |alarm: &Alarm, dt| alarm.next_ring(&dt, None).map(|(d, _, _)| d);
+
+        // first monday of the month
+        alarm.set_recurrence(Some(Recurrence::MonthWeekDay(1, DayOfWeek::Monday)));
+        assert_eq!(next(&alarm, at(2018, 12, 3, 6, 0)), Some(date(2018, 12, 3)));
+        assert_eq!(next(&alarm, at(2018, 12, 3, 8, 0)), Some(date(2019, 1, 7)));
+        assert!(alarm.must_ring(&at(2019, 1, 7, 7, 0), None));
+        assert!(!alarm.must_ring(&at(2019, 1, 14, 7, 0), None));
+        assert_eq!(alarm.to_string(), "On  07:00 monthly, 1st Mo");
+
+        // last friday of the month
+        alarm.set_recurrence(Some(Recurrence::MonthWeekDay(-1, DayOfWeek::Friday)));
+        assert_eq!(
+            next(&alarm, at(2018, 11, 1, 8, 0)),
+            Some(date(2018, 11, 30))
+        );
+        assert!(!alarm.must_ring(&at(2018, 11, 23, 7, 0), None));
+
+        // last day of the month
+        alarm.set_recurrence(Some(Recurrence::MonthDay(-1)));
+        assert_eq!(next(&alarm, at(2020, 1, 31, 8, 0)), Some(date(2020, 2, 29)));
+        assert_eq!(
+            next(&alarm, at(2018, 12, 31, 6, 0)),
+            Some(date(2018, 12, 31))
+        );
+        assert_eq!(
+            next(&alarm, at(2018, 12, 31, 8, 0)),
+            Some(date(2019, 1, 31))
+        );
+        assert_eq!(alarm.to_string(), "On  07:00 monthly, last day");
+
+        // the 31th, skipping the shorter months
+        alarm.set_recurrence(Some(Recurrence::MonthDay(31)));
+        assert_eq!(next(&alarm, at(2019, 4, 1, 8, 0)), Some(date(2019, 5, 31)));
+
+        // every 3 days starting 2026-10-01
+        let start = date(2026, 10, 1);
+        alarm.set_recurrence(Some(Recurrence::Daily { interval: 3, start }));
+        assert_eq!(next(&alarm, at(2026, 9, 20, 8, 0)), Some(start));
+        assert_eq!(
+            next(&alarm, at(2026, 10, 29, 8, 0)),
+            Some(date(2026, 10, 31))
+        );
+        assert_eq!(
+            next(&alarm, at(2026, 10, 31, 8, 0)),
+            Some(date(2026, 11, 3))
+        );
+        assert!(alarm.must_ring(&at(2026, 10, 31, 7, 0), None));
+        assert!(!alarm.must_ring(&at(2026, 11, 1, 7, 0), None));
+
+        // every other week on monday
+        alarm.mode = Mode::MONDAY;
+        let start = date(2018, 12, 26);
+        alarm.set_recurrence(Some(Recurrence::Weekly { interval: 2, start }));
+        assert_eq!(next(&alarm, at(2018, 12, 24, 8, 0)), Some(date(2019, 1, 7)));
+        assert!(!alarm.must_ring(&at(2018, 12, 31, 7, 0), None));
+        assert!(alarm.must_ring(&at(2019, 1, 7, 7, 0), None));
+        assert_eq!(alarm.to_string(), "On  07:00 every 2 weeks Mo");
+        alarm.mode = Mode::empty();
+        assert_eq!(next(&alarm, at(2018, 12, 24, 8, 0)), None);
+        alarm.mode = Mode::MONDAY;
+
+        let mut words = first_words(&alarm, ALARM_WORDS);
+        assert_eq!(Some(alarm.clone()), Alarm::from_words(&words));
+        words[5] = Some(Recurrence::MonthDay(1).as_u32() & !0xff);
+        assert_eq!(None, Alarm::from_words(&words));
+
+        alarm.set_date(Some(date(2019, 1, 1)));
+        assert_eq!(alarm.recurrence(), None);
+    }
+
+    #[test]
+    fn test_recurrence_next_day() {
+        use self::Recurrence::*;
+        let (start, mode) = (date(2026, 10, 1), Mode::MONDAY | Mode::SATURDAY);
+        let rules = [
+            Daily { interval: 1, start },
+            Daily { interval: 3, start },
+            Weekly { interval: 1, start },
+            Weekly { interval: 3, start },
+            MonthDay(31),
+            MonthWeekDay(5, DayOfWeek::Monday),
+            MonthWeekDay(-1, DayOfWeek::Sunday),
+        ];
+        for rule in rules.iter() {
+            let mut from = date(2026, 9, 1);
+            for _ in 0..200 {
+                let mut expected = from;
+                while !rule.contains(expected, mode) {
+                    expected = expected.next();
+                }
+                assert_eq!(rule.next_day(from, mode), Some(expected), "{:?}", rule);
+                from = from.next();
+            }
+        }
+        assert_eq!(
+            Weekly { interval: 2, start }.next_day(start, Mode::empty()),
+            None
+        );
+
+        // a start years later is found without walking the days
+        let start = date(2120, 2, 3);
+        let mut alarm = Alarm::parse("07:00 daily on").unwrap();
+        alarm.set_recurrence(Some(Daily { interval: 9, start }));
+        let now = DateTime::new(1_539_588_300);
+        assert_eq!(alarm.next_ring(&now, None), Some((start, 7, 0)));
+        // the tuesday of the week of the start
+        alarm.set_recurrence(Some(Weekly { interval: 5, start }));
+        alarm.mode = Mode::TUESDAY;
+        let tuesday = (date(2120, 1, 30), 7, 0);
+        assert_eq!(alarm.next_ring(&now, None), Some(tuesday));
+    }
+
+    #[test]
+    fn test_rotation() {
+        let mut alarm = Alarm {
+            is_enable: true,
+            ..Alarm::default()
+        };
+        // 4 days on, 4 days off
+        let mut rotation = Rotation::new(date(2018, 12, 29));
+        rotation.slots[0] = Slot {
+            nb_days: 4,
+            time: Some((6, 0)),
+        };
+        rotation.slots[1] = Slot {
+            nb_days: 4,
+            time: None,
+        };
+        alarm.set_rotation(Some(rotation));
+        assert_eq!(alarm.to_string(), "On  rotation 4d 06:00 4d off");
+        assert_eq!(
+            alarm.next_ring(&at(2018, 12, 20, 7, 0), None),
+            Some((date(2018, 12, 29), 6, 0))
+        );
+        assert_eq!(
+            alarm.next_ring(&at(2018, 12, 31, 7, 0), None),
+            Some((date(2019, 1, 1), 6, 0))
+        );
+        assert_eq!(
+            alarm.next_ring(&at(2019, 1, 1, 7, 0), None),
+            Some((date(2019, 1, 6), 6, 0))
+        );
+        assert!(alarm.must_ring(&at(2019, 1, 1, 6, 0), None));
+        assert!(!alarm.must_ring(&at(2019, 1, 2, 6, 0), None));
+        assert!(!alarm.must_ring(&at(2019, 1, 6, 12, 0), None));
+        assert!(alarm.must_ring(&at(2019, 1, 6, 6, 0), None));
+
+        // early, late, then rest
+        rotation.start = date(2019, 2, 27);
+        rotation.slots[0].nb_days = 2;
+        rotation.slots[1] = Slot {
+            nb_days: 2,
+            time: Some((14, 0)),
+        };
+        rotation.slots[2] = Slot {
+            nb_days: 3,
+            time: None,
+        };
+        alarm.set_rotation(Some(rotation));
+        assert_eq!(
+            alarm.next_ring(&at(2019, 2, 28, 7, 0), None),
+            Some((date(2019, 3, 1), 14, 0))
+        );
+        assert_eq!(
+            alarm.next_ring(&at(2019, 3, 2, 15, 0), None),
+            Some((date(2019, 3, 6), 6, 0))
+        );
+        assert!(alarm.must_ring(&at(2019, 3, 2, 14, 0), None));
+
+        let mut words = first_words(&alarm, ALARM_WORDS);
+        assert_eq!(Some(alarm.clone()), Alarm::from_words(&words));
+        // no day in the cycle
+        words[7] = Some(0);
+        words[8] = None;
+        assert_eq!(None, Alarm::from_words(&words));
+
+        alarm.set_recurrence(Some(Recurrence::MonthDay(1)));
+        assert_eq!(alarm.rotation(), None);
+    }
+
+    #[test]
+    fn test_catch_up() {
+        let mut alarm_manager = default_alarm_manager();
+        alarm_manager.alarms[2].is_enable = true;
+        alarm_manager.alarms[2].set_hour(7);
+        alarm_manager.alarms[2].set_min(40);
+        alarm_manager.alarms[2].mode = Mode::ONE_TIME;
+        // monday 7:25:00
+        let epoch = 1_539_588_300;
+
+        // late tick
+        assert!(alarm_manager.must_ring(&DateTime::new(epoch - 1)).is_none());
+        assert!(alarm_manager.must_ring(&DateTime::new(epoch + 2)).is_some());
+        assert_eq!(alarm_manager.ringing(), Some(DateTime::new(epoch + 2)));
+        assert!(alarm_manager.must_ring(&DateTime::new(epoch + 3)).is_none());
+        alarm_manager.dismiss();
+
+        // forward jump over the one time alarm, that is consumed
+        assert!(alarm_manager
+            .must_ring(&DateTime::new(epoch + 30 * 60))
+            .is_some());
+        assert!(!alarm_manager.alarms[2].is_enable);
+
+        // backward jump: the alarms of the period lived again ring again
+        assert!(alarm_manager
+            .must_ring(&DateTime::new(epoch - 60))
+            .is_none());
+        assert!(alarm_manager.must_ring(&DateTime::new(epoch - 1)).is_none());
+        assert!(alarm_manager.must_ring(&DateTime::new(epoch)).is_some());
+
+        // forward jump too big: nothing rings
+        assert!(alarm_manager
+            .must_ring(&DateTime::new(epoch + 86400 + 2 * 3600))
+            .is_none());
+
+        // a single evaluation of a skipped interval
+        let mut alarm_manager = default_alarm_manager();
+        assert!(alarm_manager.rings_between(epoch - 60, epoch - 1).is_none());
+        assert!(alarm_manager.rings_between(epoch - 60, epoch).is_some());
+        assert!(alarm_manager.rings_between(epoch, epoch + 60).is_none());
+        // tuesday 7:25 and wednesday 8:15
+        assert!(alarm_manager
+            .rings_between(epoch, epoch + 2 * 86400)
+            .is_some());
+        assert!(alarm_manager
+            .rings_between(epoch + 86400, epoch + 86400 + 60)
+            .is_none());
+
+        // the skip flag is consumed by the skipped interval
+        alarm_manager.alarms[0].skip_next = true;
+        assert!(alarm_manager
+            .rings_between(epoch - 60, epoch + 60)
+            .is_none());
+        assert!(!alarm_manager.alarms[0].skip_next);
+    }
+
+    #[test]
+    fn test_next_ring_epoch() {
+        let mut alarm_manager = default_alarm_manager();
+        // monday 7:00
+        let epoch = 1_539_588_300 - 25 * 60;
+        let next = alarm_manager.next_ring(&DateTime::new(epoch)).unwrap();
+        assert_eq!(next.epoch, epoch + 25 * 60);
+        assert_eq!(next.datetime, DateTime::new(epoch + 25 * 60));
+        assert_eq!(next.alarm, Some(0));
+
+        // the snooze keeps its seconds
+        assert!(alarm_manager
+            .must_ring(&DateTime::new(epoch + 25 * 60))
+            .is_some());
+        let pressed = DateTime::new(epoch + 25 * 60 + 10);
+        assert!(alarm_manager.snooze(&pressed));
+        let next = alarm_manager.next_ring(&pressed).unwrap();
+        assert_eq!(next.epoch, epoch + 34 * 60 + 10);
+        assert_eq!(next.alarm, None);
+    }
+
+    #[test]
+    fn test_pre_alarm() {
+        let mut alarm_manager = default_alarm_manager();
+        alarm_manager.alarms[0].pre_alarm = 10;
+        // monday 7:25
+        let epoch = 1_539_588_300;
+        let pre_ring = |am: &AlarmManager, min: u32, sec: u32| {
+            am.must_pre_ring(&DateTime::new(epoch - min * 60 + sec))
+        };
+        assert_eq!(pre_ring(&alarm_manager, 11, 0), None);
+        assert_eq!(pre_ring(&alarm_manager, 10, 0), Some(0));
+        assert_eq!(pre_ring(&alarm_manager, 5, 30), None);
+        assert_eq!(pre_ring(&alarm_manager, 1, 0), Some(0));
+        assert_eq!(pre_ring(&alarm_manager, 0, 0), None);
+
+        alarm_manager.alarms[0].skip_next = true;
+        assert_eq!(pre_ring(&alarm_manager, 1, 0), None);
+        alarm_manager.alarms[0].skip_next = false;
+
+        assert!(alarm_manager.must_ring(&DateTime::new(epoch)).is_some());
+        alarm_manager.alarms[1].pre_alarm = 10;
+        alarm_manager.alarms[1].set_min(30);
+        alarm_manager.alarms[1].set_hour(7);
+        alarm_manager.alarms[1].mode = Mode::MONDAY;
+        assert_eq!(pre_ring(&alarm_manager, 0, 60), None);
+        alarm_manager.dismiss();
+        assert_eq!(pre_ring(&alarm_manager, 0, 60), Some(1));
+
+        let words = first_words(&alarm_manager.alarms[1], ALARM_WORDS);
+        assert_eq!(
+            Some(alarm_manager.alarms[1].clone()),
+            Alarm::from_words(&words)
+        );
+    }
+
+    #[test]
+    fn test_nap() {
+        let mut alarm_manager = default_alarm_manager();
+        // monday 7:25
+        let epoch = 1_539_588_300;
+        // 20 min from 7:00:30 rings at 7:21
+        alarm_manager.set_nap(&DateTime::new(epoch - 1470), 20);
+        let nap = epoch - 240;
+        assert_eq!(alarm_manager.nap(), Some(DateTime::new(nap)));
+        let next = alarm_manager.next_ring(&DateTime::new(nap - 60)).unwrap();
+        assert_eq!((next.epoch, next.alarm), (nap, None));
+        assert_eq!(alarm_manager.label_of(&next).to_string(), "Nap");
+
+        let u = alarm_manager.nap_as_u32();
+        assert_eq!(Alarm::try_from(u), None);
+        let mut restored = default_alarm_manager();
+        restored.load_nap(u);
+        assert_eq!(restored.nap(), alarm_manager.nap());
+        restored.load_nap(0);
+        assert_eq!(restored.nap(), alarm_manager.nap());
+
+        assert!(alarm_manager.must_ring(&DateTime::new(nap - 1)).is_none());
+        assert!(alarm_manager.must_ring(&DateTime::new(nap)).is_some());
+        assert_eq!(alarm_manager.label(), Label::new("Nap").unwrap());
+        assert_eq!(alarm_manager.nap(), None);
+        assert_eq!(alarm_manager.nap_as_u32(), 0);
+
+        // restored long after its end
+        restored.must_ring(&DateTime::new(nap + MAX_CATCH_UP + 1));
+        assert!(restored.ringing().is_none());
+        assert_eq!(restored.nap(), None);
+
+        alarm_manager.set_nap(&DateTime::new(epoch), 10);
+        alarm_manager.set_nap(&DateTime::new(epoch), 0);
+        assert_eq!(alarm_manager.nap(), None);
+        alarm_manager.set_nap(&DateTime::new(epoch), 255);
+        let max_end = epoch + u32::from(MAX_NAP) * 60;
+        assert_eq!(alarm_manager.nap(), Some(DateTime::new(max_end)));
+    }
+
+    #[test]
+    fn test_mode_text() {
+        let mode = |s| Mode::parse(s).unwrap();
+        assert_eq!(mode("Mo-Fr"), Mode::weekdays());
+        assert_eq!(mode("weekdays"), Mode::weekdays());
+        assert_eq!(mode("Sa,Su"), Mode::SATURDAY | Mode::SUNDAY);
+        assert_eq!(mode("Sa-Mo"), Mode::SATURDAY | Mode::SUNDAY | Mode::MONDAY);
+        assert_eq!(mode("We,weekend"), mode("We,Sa,Su"));
+        assert_eq!(mode("daily"), Mode::all() - Mode::ONE_TIME);
+        assert_eq!(mode("once"), Mode::ONE_TIME);
+        assert_eq!(mode("never"), Mode::empty());
+        assert_eq!(Mode::parse("Mo,"), None);
+        assert_eq!(Mode::parse("Mo-Xy"), None);
+        assert_eq!(Mode::parse("never,Mo"), None);
+
+        assert_eq!(Mode::weekdays().to_string(), "weekdays");
+        assert_eq!(mode("Mo,Tu,Th,Fr").to_string(), "Mo,Tu,Th,Fr");
+        assert_eq!(mode("Mo,We-Fr,Su").to_string(), "Mo,We-Fr,Su");
+        assert_eq!(mode("Sa-Mo").to_string(), "Mo,Sa,Su");
+        assert_eq!(mode("once,Mo"), Mode::ONE_TIME | Mode::MONDAY);
+        assert_eq!(Mode::default().to_string(), "once,weekdays");
+        for bits in 0..=0xff {
+            let mode = Mode::from_bits_truncate(bits);
+            assert_eq!(Mode::parse(&mode.to_string()), Some(mode));
+        }
+    }
+
+    #[test]
+    fn test_alarm_text() {
+        let alarm = Alarm::parse("07:25 Mo-Fr on").unwrap();
+        assert!(alarm.is_enable);
+        assert_eq!((alarm.hour(), alarm.min()), (7, 25));
+        assert_eq!(alarm.mode, Mode::weekdays());
+        assert_eq!(alarm.as_text().to_string(), "07:25 weekdays on");
+
+        let alarm = Alarm::parse("6:05 once 2020-05-01 off").unwrap();
+        assert!(!alarm.is_enable);
+        assert_eq!(alarm.date(), Some(date(2020, 5, 1)));
+        assert_eq!(alarm.as_text().to_string(), "06:05 once 2020-05-01 off");
+        assert_eq!(Alarm::parse(&alarm.as_text().to_string()), Some(alarm));
+
+        let alarm = Alarm::parse("23:59 Tu,Sa on").unwrap();
+        assert_eq!(Alarm::parse(&alarm.as_text().to_string()), Some(alarm));
+
+        let alarm = Alarm::default();
+        assert_eq!(alarm.as_text().to_string(), "12:00 once,weekdays off");
+        assert_eq!(Alarm::parse(&alarm.as_text().to_string()), Some(alarm));
+
+        let text = "07:00 Mo,We on label=Gym ringtone=mario every=2w@2020-05-04";
+        let alarm = Alarm::parse(text).unwrap();
+        assert_eq!(alarm.label, Label::new("Gym").unwrap());
+        assert_eq!(alarm.ringtone, Ringtone::MarioThemeIntro);
+        let start = date(2020, 5, 4);
+        let recurrence = Recurrence::Weekly { interval: 2, start };
+        assert_eq!(alarm.recurrence(), Some(recurrence));
+        assert_eq!(alarm.as_text().to_string(), text);
+        for text in &[
+            "07:00 daily on every=3d@2020-05-01",
+            "07:00 daily on monthly=-1",
+            "07:00 daily on monthly=2Tu",
+            "07:00 daily off ringtone=frere-jacques",
+            "sunrise Mo,We on",
+            "sunrise+15 weekdays on",
+            "sunset-180 once on",
+        ] {
+            let alarm = Alarm::parse(text).unwrap();
+            assert_eq!(&alarm.as_text().to_string(), text);
+        }
+        assert_eq!(Alarm::parse("07:00 daily on every=0d@2020-05-01"), None);
+        assert_eq!(Alarm::parse("07:00 daily on monthly=2Xy"), None);
+        assert_eq!(Alarm::parse("07:00 daily on label=Gym!"), None);
+        assert_eq!(Alarm::parse("07:00 daily on ringtone=none"), None);
+        assert_eq!(Alarm::parse("07:00 daily on label"), None);
+        assert_eq!(Alarm::parse("sunrise+181 daily on"), None);
+        assert_eq!(Alarm::parse("sunset15 daily on"), None);
+        assert_eq!(Alarm::parse("noon daily on"), None);
+        assert_eq!(Alarm::parse("07:00 once 2020-05-01 on monthly=1"), None);
+        assert_eq!(Alarm::parse("07:00 once on monthly=1"), None);
+        assert_eq!(Alarm::parse("07:00 once,Mo on every=2w@2020-05-04"), None);
+        let text = "07:00 Mo,Sa on every=3w@2020-05-04";
+        let alarm = Alarm::parse(text).unwrap();
+        assert!(alarm.is_repeating());
+        assert_eq!(alarm.as_text().to_string(), text);
+        assert_eq!(Alarm::parse(&alarm.as_text().to_string()), Some(alarm));
+
+        assert_eq!(Alarm::parse("24:00 daily on"), None);
+        assert_eq!(Alarm::parse("07:25 daily"), None);
+        assert_eq!(Alarm::parse("07:25 daily on now"), None);
+        assert_eq!(Alarm::parse("07:25 Mo 2020-05-01 on"), None);
+    }
+
+    #[test]
+    fn test_conflict() {
+        let alarm_manager = default_alarm_manager();
+        let today = date(2018, 10, 15);
+        let conflict = |i, s| alarm_manager.conflict(i, &Alarm::parse(s).unwrap(), today);
+
+        let mut alarm = alarm_manager.alarms[0].clone();
+        assert_eq!(alarm_manager.conflict(0, &alarm, today), None);
+        assert_eq!(
+            alarm_manager.conflict(2, &alarm, today),
+            Some(Conflict::Duplicate(0))
+        );
+        alarm.is_enable = false;
+        assert_eq!(alarm_manager.conflict(2, &alarm, today), None);
+
+        assert_eq!(conflict(2, "07:28 Tu on"), Some(Conflict::Near(0)));
+        assert_eq!(conflict(2, "07:31 Tu on"), None);
+        assert_eq!(conflict(2, "07:28 We on"), None);
+        assert_eq!(conflict(2, "07:28 Tu off"), None);
+        assert_eq!(
+            conflict(2, "08:12 once 2018-10-17 on"),
+            Some(Conflict::Near(1))
+        );
+        assert_eq!(conflict(2, "08:12 once 2018-10-18 on"), None);
+        assert_eq!(conflict(2, "07:27 once on"), Some(Conflict::Near(0)));
+        assert_eq!(conflict(2, "08:12 once on"), None);
+
+        // the time of a solar or rotation alarm is not its hour and min
+        let solar = Alarm::parse("sunrise+15 weekdays on").unwrap();
+        let mut other = solar.clone();
+        other.hour = 3;
+        assert!(other.is_duplicate_of(&solar));
+        other.solar = Alarm::parse("sunrise+20 weekdays on").unwrap().solar;
+        assert!(!other.is_duplicate_of(&solar));
+        let mut other = solar.clone();
+        other.solar = None;
+        assert!(!other.is_duplicate_of(&solar));
+        let mut rotation = other.clone();
+        rotation.rotation = Some(Rotation::new(today));
+        assert!(!rotation.is_duplicate_of(&other));
+        let mut other = rotation.clone();
+        other.min = 59;
+        assert!(other.is_duplicate_of(&rotation));
+        other.rotation = Some(Rotation::new(today.next()));
+        assert!(!other.is_duplicate_of(&rotation));
+    }
+
+    #[test]
+    fn test_bedtime() {
+        let mut alarm_manager = default_alarm_manager();
+        // monday 7:25, bedtime sunday 23:25
+        let epoch = 1_539_588_300;
+        let bedtime = epoch - 8 * 3600;
+        assert!(!alarm_manager.must_remind_bedtime(&DateTime::new(bedtime)));
+        alarm_manager.sleep_config.reminder = true;
+        assert!(!alarm_manager.must_remind_bedtime(&DateTime::new(bedtime - 60)));
+        assert!(alarm_manager.must_remind_bedtime(&DateTime::new(bedtime)));
+        assert!(!alarm_manager.must_remind_bedtime(&DateTime::new(bedtime + 60)));
+    }
+
+    #[test]
+    fn test_suspension() {
+        let mut alarm_manager = default_alarm_manager();
+        alarm_manager.alarms[1].is_enable = false;
+        // monday 7:25, suspended until tuesday
+        let epoch = 1_539_588_300;
+        alarm_manager.suspended_until = Some(date(2018, 10, 16));
+        assert!(alarm_manager.is_suspended(date(2018, 10, 15)));
+        assert!(!alarm_manager.is_suspended(date(2018, 10, 17)));
+        let next = alarm_manager.next_ring(&DateTime::new(epoch - 60)).unwrap();
+        assert_eq!(next.epoch, epoch + 3 * 86400);
+        assert!(alarm_manager.must_ring(&DateTime::new(epoch)).is_none());
+        assert!(alarm_manager
+            .must_ring(&DateTime::new(epoch + 86400))
+            .is_none());
+        assert!(alarm_manager
+            .must_ring(&DateTime::new(epoch + 3 * 86400))
+            .is_some());
+
+        // a one time alarm during the suspension never rings
+        alarm_manager.alarms[0].mode = Mode::ONE_TIME;
+        alarm_manager.alarms[0].date = Some(date(2018, 10, 16));
+        assert_eq!(alarm_manager.next_ring(&DateTime::new(epoch)), None);
+        alarm_manager.suspended_until = None;
+        assert!(alarm_manager.next_ring(&DateTime::new(epoch)).is_some());
+    }
+
+    #[test]
+    fn test_holidays() {
+        let mut alarm_manager = default_alarm_manager();
+        alarm_manager.alarms[1].is_enable = false;
+        alarm_manager.alarms[0].holidays = Some(Country::France);
+        let alarm = &alarm_manager.alarms[0];
+        let words = first_words(alarm, ALARM_WORDS);
+        assert_eq!(Alarm::from_words(&words).as_ref(), Some(alarm));
+
+        // thursday 2018-11-01 7:25, All Saints' Day
+        let epoch = 1_539_588_300 + 17 * 86400;
+        let next = alarm_manager.next_ring(&DateTime::new(epoch - 60)).unwrap();
+        assert_eq!(next.epoch, epoch + 86400);
+        assert!(alarm_manager.must_ring(&DateTime::new(epoch)).is_none());
+        alarm_manager.alarms[0].holidays = Some(Country::UnitedStates);
+        let next = alarm_manager.next_ring(&DateTime::new(epoch - 60)).unwrap();
+        assert_eq!(next.epoch, epoch);
+    }
+
+    #[test]
+    fn test_profiles() {
+        let mut alarm_manager = default_alarm_manager();
+        let mut store = Store::new(RamFlash::new(1024));
+        assert_eq!(store.save(alarm_manager.records()), Ok(()));
+        // monday 7:25
+        let epoch = 1_539_588_300;
+
+        // the profile never saved has the default alarms
+        alarm_manager.activate_profile(AlarmManager::load_profile(&store, 1));
+        assert_eq!(alarm_manager.active_profile, 1);
+        assert_eq!(
+            alarm_manager.alarms,
+            AlarmManager::<NbAlarms>::default().alarms
+        );
+        assert_eq!(alarm_manager.next_ring(&DateTime::new(epoch - 60)), None);
+        let alarm = Alarm::parse("08:00 Mo on").unwrap();
+        alarm_manager.alarms[0] = alarm.clone();
+        let exception = Exception::new(date(2018, 10, 22), date(2018, 10, 22));
+        alarm_manager.exceptions.push(exception).unwrap();
+        assert_eq!(store.save(alarm_manager.records()), Ok(()));
+        let next = alarm_manager.next_ring(&DateTime::new(epoch - 60)).unwrap();
+        assert_eq!(next.epoch, epoch + 35 * 60);
+
+        // back to the first profile, the store keeping both
+        alarm_manager.activate_profile(AlarmManager::load_profile(&store, 0));
+        assert_eq!(alarm_manager.alarms, default_alarm_manager().alarms);
+        assert!(alarm_manager.exceptions.is_empty());
+        assert_eq!(store.save(alarm_manager.records()), Ok(()));
+        let loaded: AlarmManager = AlarmManager::load(&store);
+        assert_eq!(loaded.active_profile, 0);
+        assert_eq!(loaded.alarms, alarm_manager.alarms);
+        let profile: AlarmManager = AlarmManager::load_profile(&store, 1);
+        assert_eq!(profile.alarms[0], alarm);
+        assert_eq!(profile.exceptions, [exception]);
+
+        // a snoozed alarm of the previous profile is dismissed
+        assert!(alarm_manager.must_ring(&DateTime::new(epoch)).is_some());
+        assert!(alarm_manager.snooze(&DateTime::new(epoch)));
+        alarm_manager.activate_profile(profile);
+        assert_eq!(alarm_manager.snoozed_until(), None);
+        assert!(alarm_manager
+            .must_ring(&DateTime::new(epoch + 35 * 60))
+            .is_some());
+    }
+
+    #[test]
+    fn test_chime() {
+        let mut alarm_manager = default_alarm_manager();
+        alarm_manager.chime_config.kind = ChimeKind::Beep;
+        // monday 7:25, chiming at 8:00
+        let epoch = 1_539_588_300;
+        let eight = DateTime::new(epoch + 35 * 60);
+        assert!(alarm_manager.must_chime(&eight).is_some());
+        assert!(alarm_manager.must_ring(&DateTime::new(epoch)).is_some());
+        assert_eq!(alarm_manager.must_chime(&eight), None);
+        assert!(alarm_manager.snooze(&DateTime::new(epoch + 60)));
+        assert_eq!(alarm_manager.must_chime(&eight), None);
+        alarm_manager.dismiss();
+        assert!(alarm_manager.must_chime(&eight).is_some());
+    }
+
+    #[test]
+    fn test_solar() {
+        let mut alarm_manager = default_alarm_manager();
+        let alarm = &mut alarm_manager.alarms[0];
+        alarm.set_solar(Some(Solar {
+            event: SunEvent::Sunrise,
+            offset: 15,
+        }));
+        assert_eq!(alarm.to_string(), "On  sunrise+15 Mo Tu Th Fr");
+        let words = first_words(alarm, ALARM_WORDS);
+        assert_eq!(Alarm::from_words(&words).as_ref(), Some(&*alarm));
+        assert_eq!(Solar::from_u32(1 | 1000 << 2), None);
+        assert_eq!(alarm.as_text().to_string(), "sunrise+15 Mo,Tu,Th,Fr on");
+        let parsed = Alarm::parse(&alarm.as_text().to_string()).unwrap();
+        assert_eq!(parsed.solar(), alarm.solar());
+
+        // monday 2018-10-15 0:00, the wednesday alarm ringing 8:15
+        let midnight = DateTime::new(1_539_561_600);
+        assert_eq!(alarm_manager.alarms[0].next_ring(&midnight, None), None);
+        let next = alarm_manager.next_ring(&midnight).unwrap();
+        assert_eq!(next.alarm, Some(1));
+        alarm_manager.location = Some(Location {
+            latitude: 4886,
+            longitude: 235,
+            utc_offset: 120,
+        });
+        let next = alarm_manager.next_ring(&midnight).unwrap();
+        assert_eq!((next.datetime.hour, next.datetime.min), (8, 25));
+        assert!(alarm_manager.must_ring(&next.datetime).is_some());
+        alarm_manager.dismiss();
+        let next = alarm_manager.next_ring(&next.datetime).unwrap();
+        assert_eq!(next.datetime.to_string(), "2018-10-16 08:27:00 (Tuesday)");
+
+        // the rotation replaces the solar time
+        let mut rotation = Rotation::new(date(2018, 10, 15));
+        rotation.slots[0] = Slot {
+            nb_days: 1,
+            time: Some((7, 0)),
+        };
+        let alarm = &mut alarm_manager.alarms[0];
+        alarm.set_rotation(Some(rotation));
+        assert_eq!(alarm.solar(), None);
+    }
 }
@@ -0,0 +1,300 @@
+use crate::datetime::DateTime;
+use core::fmt;
+
+const BEEP_MS: u16 = 150;
+const CUCKOO_MS: u16 = 250;
+const WESTMINSTER_MS: u16 = 500;
+const STRIKE_MS: u16 = 1000;
+
+/// What is played when chiming.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChimeKind {
+    Off,
+    /// A single beep.
+    Beep,
+    /// Cuckoos counting the hour, a single one at the quarters.
+    Cuckoo,
+    /// The Westminster quarters, the hour being then struck.
+    Westminster,
+}
+impl Default for ChimeKind {
+    fn default() -> Self {
+        ChimeKind::Off
+    }
+}
+impl ChimeKind {
+    pub fn next(&mut self) {
+        use self::ChimeKind::*;
+        *self = match *self {
+            Off => Beep,
+            Beep => Cuckoo,
+            Cuckoo => Westminster,
+            Westminster => Off,
+        }
+    }
+    fn from_u8(u: u8) -> Option<Self> {
+        use self::ChimeKind::*;
+        Some(match u {
+            0 => Off,
+            1 => Beep,
+            2 => Cuckoo,
+            3 => Westminster,
+            _ => return None,
+        })
+    }
+}
+impl fmt::Display for ChimeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::ChimeKind::*;
+        f.write_str(match self {
+            Off => "off",
+            Beep => "beep",
+            Cuckoo => "cuckoo",
+            Westminster => "westminster",
+        })
+    }
+}
+
+/// The chime configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChimeConfig {
+    pub kind: ChimeKind,
+    /// also chime at the quarters, not only at the hours
+    pub quarters: bool,
+    /// beginning of the quiet hours, unit: hour
+    pub quiet_from: u8,
+    /// end of the quiet hours, excluded, unit: hour
+    pub quiet_until: u8,
+}
+impl Default for ChimeConfig {
+    fn default() -> Self {
+        Self {
+            kind: ChimeKind::default(),
+            quarters: false,
+            quiet_from: 22,
+            quiet_until: 7,
+        }
+    }
+}
+impl ChimeConfig {
+    /// Returns true if `hour` is in the quiet hours, that may span
+    /// midnight. There are no quiet hours if they begin and end at
+    /// the same hour.
+    pub fn is_quiet(&self, hour: u8) -> bool {
+        if self.quiet_from <= self.quiet_until {
+            self.quiet_from <= hour && hour < self.quiet_until
+        } else {
+            hour >= self.quiet_from || hour < self.quiet_until
+        }
+    }
+    /// The chime to play at `datetime`, if any.
+    pub fn chime_at(&self, datetime: &DateTime) -> Option<Chime> {
+        if self.kind == ChimeKind::Off
+            || datetime.sec != 0
+            || datetime.min % 15 != 0
+            || (!self.quarters && datetime.min != 0)
+            || self.is_quiet(datetime.hour)
+        {
+            return None;
+        }
+        Some(Chime {
+            kind: self.kind,
+            quarter: datetime.min / 15,
+            hour: (datetime.hour + 11) % 12 + 1,
+        })
+    }
+    pub fn as_u32(&self) -> u32 {
+        self.kind as u32
+            | (self.quarters as u32) << 4
+            | u32::from(self.quiet_from) << 8
+            | u32::from(self.quiet_until) << 16
+            | 1 << 24
+    }
+    pub fn try_from(u: u32) -> Option<Self> {
+        let (quiet_from, quiet_until) = ((u >> 8) as u8, (u >> 16) as u8);
+        if u >> 24 != 1 || quiet_from >= 24 || quiet_until >= 24 {
+            return None;
+        }
+        Some(Self {
+            kind: ChimeKind::from_u8((u & 0xf) as u8)?,
+            quarters: u & 1 << 4 != 0,
+            quiet_from,
+            quiet_until,
+        })
+    }
+}
+
+/// The tones of the chimes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tone {
+    E5,
+    D5,
+    C5,
+    G4,
+    C4,
+}
+
+/// A note of a chime, `tone` being `None` for a rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note {
+    pub tone: Option<Tone>,
+    /// unit: ms
+    pub ms: u16,
+}
+fn note(tone: Tone, ms: u16) -> Note {
+    Note {
+        tone: Some(tone),
+        ms,
+    }
+}
+fn rest(ms: u16) -> Note {
+    Note { tone: None, ms }
+}
+
+/// The changes of the Westminster quarters, transposed in C.
+const CHANGES: [[Tone; 4]; 5] = {
+    use self::Tone::*;
+    [
+        [E5, D5, C5, G4],
+        [C5, E5, D5, G4],
+        [C5, D5, E5, C5],
+        [E5, C5, D5, G4],
+        [G4, D5, E5, C5],
+    ]
+};
+
+/// The changes played at the given quarter, 0 for the hour.
+fn westminster_changes(quarter: u8) -> &'static [usize] {
+    match quarter {
+        1 => &[0],
+        2 => &[1, 2],
+        3 => &[3, 4, 0],
+        _ => &[1, 2, 3, 4],
+    }
+}
+
+/// A chime to play.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chime {
+    pub kind: ChimeKind,
+    /// quarter of the hour, 0 at the hour
+    pub quarter: u8,
+    /// hour on a 12 hour dial, from 1 to 12
+    pub hour: u8,
+}
+impl Chime {
+    /// The `i`th note of the chime, `None` after the end.
+    pub fn note(&self, i: usize) -> Option<Note> {
+        use self::Tone::*;
+        match self.kind {
+            ChimeKind::Off => None,
+            ChimeKind::Beep if i == 0 => Some(note(E5, BEEP_MS)),
+            ChimeKind::Beep => None,
+            ChimeKind::Cuckoo => {
+                let nb = if self.quarter == 0 { self.hour } else { 1 };
+                if i >= 3 * usize::from(nb) {
+                    return None;
+                }
+                Some(match i % 3 {
+                    0 => note(E5, CUCKOO_MS),
+                    1 => note(C5, CUCKOO_MS),
+                    _ => rest(2 * CUCKOO_MS),
+                })
+            }
+            ChimeKind::Westminster => {
+                // each change is followed by a rest
+                let changes = westminster_changes(self.quarter);
+                if let Some(&change) = changes.get(i / 5) {
+                    return Some(match CHANGES[change].get(i % 5) {
+                        Some(&tone) => note(tone, WESTMINSTER_MS),
+                        None => rest(WESTMINSTER_MS),
+                    });
+                }
+                let i = i - 5 * changes.len();
+                if self.quarter != 0 || i >= 2 * usize::from(self.hour) {
+                    None
+                } else if i % 2 == 0 {
+                    Some(note(C4, STRIKE_MS))
+                } else {
+                    Some(rest(WESTMINSTER_MS))
+                }
+            }
+        }
+    }
+    /// The notes of the chime.
+    pub fn notes(self) -> impl Iterator<Item = Note> {
+        let mut i = 0;
+        core::iter::from_fn(move || {
+            i += 1;
+            self.note(i - 1)
+        })
+    }
+    /// unit: ms
+    pub fn duration_ms(&self) -> u32 {
+        self.notes().map(|n| u32::from(n.ms)).sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(hour: u8, min: u8, sec: u8) -> DateTime {
+        let mut dt = DateTime::new(1_539_561_600);
+        dt.hour = hour;
+        dt.min = min;
+        dt.sec = sec;
+        dt
+    }
+
+    #[test]
+    fn test_chime() {
+        let mut config = ChimeConfig {
+            kind: ChimeKind::Cuckoo,
+            ..ChimeConfig::default()
+        };
+        assert_eq!(Some(config), ChimeConfig::try_from(config.as_u32()));
+        assert_eq!(None, ChimeConfig::try_from(0));
+        assert_eq!(
+            config.chime_at(&at(15, 0, 0)),
+            Some(Chime {
+                kind: ChimeKind::Cuckoo,
+                quarter: 0,
+                hour: 3,
+            })
+        );
+        assert_eq!(config.chime_at(&at(15, 0, 1)), None);
+        assert_eq!(config.chime_at(&at(15, 30, 0)), None);
+        assert_eq!(config.chime_at(&at(22, 0, 0)), None);
+        assert_eq!(config.chime_at(&at(6, 0, 0)), None);
+        assert_eq!(config.chime_at(&at(7, 0, 0)).unwrap().hour, 7);
+        assert_eq!(config.chime_at(&at(12, 0, 0)).unwrap().hour, 12);
+
+        config.quarters = true;
+        config.quiet_from = 1;
+        config.quiet_until = 1;
+        assert_eq!(config.chime_at(&at(0, 45, 0)).unwrap().quarter, 3);
+        assert_eq!(config.chime_at(&at(0, 50, 0)), None);
+        config.quiet_until = 5;
+        assert!(!config.is_quiet(0) && config.is_quiet(1) && !config.is_quiet(5));
+        config.kind = ChimeKind::Off;
+        assert_eq!(config.chime_at(&at(12, 0, 0)), None);
+    }
+
+    #[test]
+    fn test_notes() {
+        let chime = |kind, quarter, hour| Chime {
+            kind,
+            quarter,
+            hour,
+        };
+        assert_eq!(chime(ChimeKind::Beep, 0, 3).notes().count(), 1);
+        assert_eq!(chime(ChimeKind::Cuckoo, 0, 3).notes().count(), 9);
+        assert_eq!(chime(ChimeKind::Cuckoo, 2, 3).notes().count(), 3);
+        assert_eq!(chime(ChimeKind::Westminster, 1, 3).duration_ms(), 2500);
+        let hour = chime(ChimeKind::Westminster, 0, 3);
+        assert_eq!(hour.notes().count(), 4 * 5 + 2 * 3);
+        assert_eq!(hour.note(20), Some(note(Tone::C4, STRIKE_MS)));
+        assert_eq!(hour.duration_ms(), 10_000 + 3 * 1500);
+    }
+}
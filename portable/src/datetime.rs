@@ -60,21 +60,14 @@ impl ::core::fmt::Display for DayOfWeek {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct DateTime {
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
     pub year: u16,
     pub month: u8,
     pub day: u8,
-    pub hour: u8,
-    pub min: u8,
-    pub sec: u8,
-    pub day_of_week: DayOfWeek,
 }
-impl DateTime {
-    pub fn new(epoch: u32) -> DateTime {
-        let mut days = epoch / 86400;
-        let time = epoch % 86400;
-        let day_of_week = DayOfWeek::from_days_since_epoch(days);
+impl Date {
+    pub fn new(mut days: u32) -> Date {
         let mut year = 1970;
         let mut is_leap_year;
 
@@ -98,17 +91,14 @@ impl DateTime {
             days -= nb;
             month += 1;
         }
-        DateTime {
+        Date {
             year,
             month,
             day: (days + 1) as u8,
-            hour: (time / 60 / 60) as u8,
-            min: (time / 60 % 60) as u8,
-            sec: (time % 60) as u8,
-            day_of_week,
         }
     }
-    pub fn to_epoch(&self) -> Option<u32> {
+    /// Number of days since 1970-01-01.
+    pub fn to_days(&self) -> Option<u32> {
         if self.year < 1970 || self.month == 0 || self.month > 12 || self.day == 0 {
             return None;
         }
@@ -127,11 +117,398 @@ impl DateTime {
         {
             days = days.checked_add(m)?;
         }
+        Some(days)
+    }
+    /// Parses a valid date as "2018-10-20".
+    pub fn parse(s: &str) -> Option<Date> {
+        let mut parts = s.splitn(3, '-');
+        let date = Date {
+            year: parts.next()?.parse().ok()?,
+            month: parts.next()?.parse().ok()?,
+            day: parts.next()?.parse().ok()?,
+        };
+        if date.is_valid() {
+            Some(date)
+        } else {
+            None
+        }
+    }
+    pub fn nb_days_in_month(&self) -> u8 {
+        days_in_month(is_leap(self.year))[(self.month as usize + 11) % 12] as u8
+    }
+    pub fn is_valid(&self) -> bool {
+        self.month >= 1 && self.month <= 12 && self.day >= 1 && self.day <= self.nb_days_in_month()
+    }
+    pub fn day_of_week(&self) -> DayOfWeek {
+        // Sakamoto's method
+        static OFFSETS: [u16; 12] = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+        use self::DayOfWeek::*;
+        let y = if self.month < 3 {
+            self.year - 1
+        } else {
+            self.year
+        };
+        let offset = OFFSETS[(self.month as usize + 11) % 12];
+        match (y + y / 4 - y / 100 + y / 400 + offset + u16::from(self.day)) % 7 {
+            0 => Sunday,
+            1 => Monday,
+            2 => Tuesday,
+            3 => Wednesday,
+            4 => Thursday,
+            5 => Friday,
+            6 => Saturday,
+            _ => unreachable!(),
+        }
+    }
+    pub fn next(self) -> Self {
+        if self.day < self.nb_days_in_month() {
+            Date {
+                day: self.day + 1,
+                ..self
+            }
+        } else if self.month < 12 {
+            Date {
+                month: self.month + 1,
+                day: 1,
+                ..self
+            }
+        } else {
+            Date {
+                year: self.year + 1,
+                month: 1,
+                day: 1,
+            }
+        }
+    }
+    pub fn prev(self) -> Self {
+        if self.day > 1 {
+            Date {
+                day: self.day - 1,
+                ..self
+            }
+        } else if self.month > 1 {
+            let month = Date {
+                month: self.month - 1,
+                ..self
+            };
+            Date {
+                day: month.nb_days_in_month(),
+                ..month
+            }
+        } else {
+            Date {
+                year: self.year - 1,
+                month: 12,
+                day: 31,
+            }
+        }
+    }
+}
+
+/// The Easter sunday of `year` (Gregorian calendar), computed with
+/// the anonymous Gregorian algorithm.
+pub fn easter(year: u16) -> Date {
+    let y = u32::from(year);
+    let (a, b, c) = (y % 19, y / 100, y % 100);
+    let (d, e) = (b / 4, b % 4);
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let (i, k) = (c / 4, c % 4);
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let n = h + l - 7 * m + 114;
+    Date {
+        year,
+        month: (n / 31) as u8,
+        day: (n % 31 + 1) as u8,
+    }
+}
 
+/// The countries with built-in public holiday rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Country {
+    France,
+    /// the nationwide holidays
+    Germany,
+    /// the bank holidays of England and Wales
+    UnitedKingdom,
+    /// the federal holidays, observed the closest weekday
+    UnitedStates,
+}
+impl Country {
+    /// The next country, `None` after the last one.
+    pub fn next(country: Option<Country>) -> Option<Country> {
+        use self::Country::*;
+        match country {
+            None => Some(France),
+            Some(France) => Some(Germany),
+            Some(Germany) => Some(UnitedKingdom),
+            Some(UnitedKingdom) => Some(UnitedStates),
+            Some(UnitedStates) => None,
+        }
+    }
+    pub fn as_u8(self) -> u8 {
+        self as u8 + 1
+    }
+    /// Decodes `as_u8`, 0 being no country.
+    pub fn from_u8(u: u8) -> Option<Option<Country>> {
+        use self::Country::*;
+        Some(match u {
+            0 => None,
+            1 => Some(France),
+            2 => Some(Germany),
+            3 => Some(UnitedKingdom),
+            4 => Some(UnitedStates),
+            _ => return None,
+        })
+    }
+    pub fn is_holiday(self, date: Date) -> bool {
+        use self::DayOfWeek::*;
+        let from_easter = match (date.to_days(), easter(date.year).to_days()) {
+            (Some(day), Some(easter)) => i64::from(day) - i64::from(easter),
+            _ => return false,
+        };
+        let dow = date.day_of_week();
+        // the day is the nth of its weekday in the month
+        let nth = (date.day - 1) / 7 + 1;
+        let is_last = date.day + 7 > date.nb_days_in_month();
+        match self {
+            Country::France => match (date.month, date.day) {
+                (1, 1) | (5, 1) | (5, 8) | (7, 14) | (8, 15) | (11, 1) | (11, 11) | (12, 25) => {
+                    true
+                }
+                // Easter monday, Ascension, Whit monday
+                _ => from_easter == 1 || from_easter == 39 || from_easter == 50,
+            },
+            Country::Germany => match (date.month, date.day) {
+                (1, 1) | (5, 1) | (10, 3) | (12, 25) | (12, 26) => true,
+                // Good friday, Easter monday, Ascension, Whit monday
+                _ => [-2, 1, 39, 50].contains(&from_easter),
+            },
+            Country::UnitedKingdom => match (date.month, date.day) {
+                (1, 1) | (12, 25) | (12, 26) => dow != Saturday && dow != Sunday,
+                // substitute days of the holidays falling on a weekend
+                (1, 2) | (1, 3) => dow == Monday,
+                (12, 27) | (12, 28) => dow == Monday || dow == Tuesday,
+                // early May, spring and summer bank holidays
+                (5, _) if dow == Monday => nth == 1 || is_last,
+                (8, _) if dow == Monday => is_last,
+                // Good friday, Easter monday
+                _ => from_easter == -2 || from_easter == 1,
+            },
+            Country::UnitedStates => {
+                let observed = match dow {
+                    Saturday | Sunday => false,
+                    Friday => is_us_fixed_holiday(date) || is_us_fixed_holiday(date.next()),
+                    Monday => is_us_fixed_holiday(date) || is_us_fixed_holiday(date.prev()),
+                    _ => is_us_fixed_holiday(date),
+                };
+                observed
+                    || match (date.month, dow) {
+                        // Martin Luther King, Presidents' day
+                        (1, Monday) | (2, Monday) => nth == 3,
+                        // Memorial day
+                        (5, Monday) => is_last,
+                        // Labor day
+                        (9, Monday) => nth == 1,
+                        // Columbus day
+                        (10, Monday) => nth == 2,
+                        // Thanksgiving
+                        (11, Thursday) => nth == 4,
+                        _ => false,
+                    }
+            }
+        }
+    }
+}
+impl ::core::fmt::Display for Country {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> Result<(), ::core::fmt::Error> {
+        f.write_str(match self {
+            Country::France => "France",
+            Country::Germany => "Germany",
+            Country::UnitedKingdom => "UK",
+            Country::UnitedStates => "US",
+        })
+    }
+}
+
+/// The US federal holidays of fixed date, before being observed.
+fn is_us_fixed_holiday(date: Date) -> bool {
+    match (date.month, date.day) {
+        (1, 1) | (7, 4) | (11, 11) | (12, 25) => true,
+        // Juneteenth
+        (6, 19) => date.year >= 2021,
+        _ => false,
+    }
+}
+impl ::core::fmt::Display for Date {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> Result<(), ::core::fmt::Error> {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Days from 1970-01-01 to 2000-01-01, the J2000 epoch being at noon.
+const J2000_DAYS: i32 = 10_957;
+
+/// The place of the clock, to compute the sunrise and the sunset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    /// unit: 1/100 degree, positive to the north
+    pub latitude: i16,
+    /// unit: 1/100 degree, positive to the east
+    pub longitude: i16,
+    /// offset of the time of the clock from UTC, unit: min
+    pub utc_offset: i16,
+}
+impl Default for Location {
+    fn default() -> Self {
+        Self {
+            latitude: 0,
+            longitude: 0,
+            utc_offset: 0,
+        }
+    }
+}
+/// Largest latitude, unit: 1/100 degree.
+pub const MAX_LATITUDE: i16 = 9000;
+/// Largest longitude, unit: 1/100 degree.
+pub const MAX_LONGITUDE: i16 = 18000;
+/// Largest offset from UTC, unit: min.
+pub const MAX_UTC_OFFSET: i16 = 14 * 60;
+impl Location {
+    pub fn is_valid(&self) -> bool {
+        self.latitude.abs() <= MAX_LATITUDE
+            && self.longitude.abs() <= MAX_LONGITUDE
+            && self.utc_offset.abs() <= MAX_UTC_OFFSET
+    }
+    pub fn as_words(&self) -> [u32; 2] {
+        [
+            u32::from(self.latitude as u16) | u32::from(self.longitude as u16) << 16,
+            u32::from(self.utc_offset as u16) | 1 << 16,
+        ]
+    }
+    pub fn from_words(words: [u32; 2]) -> Option<Self> {
+        if words[1] >> 16 != 1 {
+            return None;
+        }
+        let location = Self {
+            latitude: words[0] as u16 as i16,
+            longitude: (words[0] >> 16) as u16 as i16,
+            utc_offset: words[1] as u16 as i16,
+        };
+        if location.is_valid() {
+            Some(location)
+        } else {
+            None
+        }
+    }
+    pub fn latitude(&self) -> impl ::core::fmt::Display {
+        Degrees(self.latitude, 'N', 'S')
+    }
+    pub fn longitude(&self) -> impl ::core::fmt::Display {
+        Degrees(self.longitude, 'E', 'W')
+    }
+}
+
+/// Formats hundredths of degree.
+struct Degrees(i16, char, char);
+impl ::core::fmt::Display for Degrees {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> Result<(), ::core::fmt::Error> {
+        let (abs, side) = if self.0 < 0 {
+            (-i32::from(self.0), self.2)
+        } else {
+            (i32::from(self.0), self.1)
+        };
+        write!(f, "{}.{:02} {}", abs / 100, abs % 100, side)
+    }
+}
+
+/// The sun crossing the horizon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SunEvent {
+    Sunrise,
+    Sunset,
+}
+impl ::core::fmt::Display for SunEvent {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> Result<(), ::core::fmt::Error> {
+        f.write_str(match self {
+            SunEvent::Sunrise => "sunrise",
+            SunEvent::Sunset => "sunset",
+        })
+    }
+}
+
+/// The (hour, min) of `event` on `date` at `location`, in the time of
+/// the clock, computed with the sunrise equation. `None` during the
+/// polar days and nights.
+pub fn sun_event(date: Date, location: Location, event: SunEvent) -> Option<(u8, u8)> {
+    use libm::{acosf, asinf, cosf, roundf, sinf};
+    let rad = |deg: f32| deg.to_radians();
+    let n = date.to_days()? as i32 - J2000_DAYS;
+    let longitude = f32::from(location.longitude) / 100.;
+    let latitude = rad(f32::from(location.latitude) / 100.);
+    // the fraction of day is kept apart from `n` for precision
+    let noon = -longitude / 360.;
+    let anomaly = 357.5291 + 0.985_600_3 * (n as f32 + noon);
+    let m = rad(anomaly % 360.);
+    let center = 1.9148 * sinf(m) + 0.02 * sinf(2. * m) + 0.0003 * sinf(3. * m);
+    let lambda = rad((anomaly + center + 180. + 102.9372) % 360.);
+    let transit = noon + 0.0053 * sinf(m) - 0.0069 * sinf(2. * lambda);
+    let declination = asinf(sinf(lambda) * sinf(rad(23.4397)));
+    let cos_hour_angle = (sinf(rad(-0.833)) - sinf(latitude) * sinf(declination))
+        / (cosf(latitude) * cosf(declination));
+    if !(-1. ..=1.).contains(&cos_hour_angle) {
+        return None;
+    }
+    let half_day = acosf(cos_hour_angle).to_degrees() / 360.;
+    let day = match event {
+        SunEvent::Sunrise => transit - half_day,
+        SunEvent::Sunset => transit + half_day,
+    };
+    let utc = roundf(720. + day * 1440.) as i32;
+    let min = (utc + i32::from(location.utc_offset)).rem_euclid(24 * 60);
+    Some(((min / 60) as u8, (min % 60) as u8))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub min: u8,
+    pub sec: u8,
+    pub day_of_week: DayOfWeek,
+}
+impl DateTime {
+    pub fn new(epoch: u32) -> DateTime {
+        let days = epoch / 86400;
+        let time = epoch % 86400;
+        let date = Date::new(days);
+        DateTime {
+            year: date.year,
+            month: date.month,
+            day: date.day,
+            hour: (time / 60 / 60) as u8,
+            min: (time / 60 % 60) as u8,
+            sec: (time % 60) as u8,
+            day_of_week: DayOfWeek::from_days_since_epoch(days),
+        }
+    }
+    pub fn to_epoch(&self) -> Option<u32> {
+        let days = self.date().to_days()?;
         let time = u32::from(self.hour) * 3600 + u32::from(self.min) * 60 + u32::from(self.sec);
         let epoch = days.checked_mul(86400)?.checked_add(time)?;
         Some(epoch)
     }
+    pub fn date(&self) -> Date {
+        Date {
+            year: self.year,
+            month: self.month,
+            day: self.day,
+        }
+    }
 }
 impl ::core::fmt::Display for DateTime {
     fn fmt(&self, f: &mut ::core::fmt::Formatter) -> Result<(), ::core::fmt::Error> {
@@ -146,6 +523,7 @@ impl ::core::fmt::Display for DateTime {
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::string::ToString;
 
     const EPOCH: DateTime = DateTime {
         year: 1970,
@@ -206,4 +584,173 @@ mod test {
             None
         );
     }
+
+    #[test]
+    fn test_date() {
+        let date = Date {
+            year: 2018,
+            month: 10,
+            day: 20,
+        };
+        assert_eq!(date.to_days(), Some(1540052501 / 86400));
+        assert_eq!(Date::new(1540052501 / 86400), date);
+        assert_eq!(date.day_of_week(), DayOfWeek::Saturday);
+        assert_eq!(EPOCH.date().day_of_week(), DayOfWeek::Thursday);
+        assert_eq!(END_OF_TIME.date().day_of_week(), DayOfWeek::Sunday);
+        assert_eq!(
+            Date {
+                year: 2000,
+                month: 2,
+                day: 29,
+            }
+            .day_of_week(),
+            DayOfWeek::Tuesday
+        );
+        assert_eq!(
+            Date {
+                year: 1969,
+                month: 12,
+                day: 31,
+            }
+            .to_days(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_date_next() {
+        let mut date = Date {
+            year: 2019,
+            month: 12,
+            day: 30,
+        };
+        let mut days = date.to_days().unwrap();
+        for _ in 0..800 {
+            let next = date.next();
+            days += 1;
+            assert!(next.is_valid());
+            assert_eq!(Date::new(days), next);
+            assert_eq!(next.day_of_week(), DayOfWeek::from_days_since_epoch(days));
+            date = next;
+        }
+        assert!(!Date {
+            year: 2019,
+            month: 2,
+            day: 29,
+        }
+        .is_valid());
+        assert!(Date {
+            year: 2020,
+            month: 2,
+            day: 29,
+        }
+        .is_valid());
+    }
+
+    #[test]
+    fn test_date_parse() {
+        let date = Date {
+            year: 2018,
+            month: 10,
+            day: 20,
+        };
+        assert_eq!(Date::parse("2018-10-20"), Some(date));
+        assert_eq!(Date::parse(&date.to_string()), Some(date));
+        assert_eq!(Date::parse("2019-02-29"), None);
+        assert_eq!(Date::parse("2018-10"), None);
+        assert_eq!(Date::parse("2018-10-20-1"), None);
+    }
+
+    #[test]
+    fn test_holidays() {
+        let d = |s: &str| Date::parse(s).unwrap();
+        assert_eq!(d("2019-01-01").prev(), d("2018-12-31"));
+        assert_eq!(d("2020-03-01").prev(), d("2020-02-29"));
+        for &easter_day in &["2008-03-23", "2018-04-01", "2019-04-21", "2038-04-25"] {
+            assert_eq!(easter(d(easter_day).year), d(easter_day));
+        }
+
+        let is_holiday =
+            |country: Country, days: &[&str]| days.iter().all(|&day| country.is_holiday(d(day)));
+        let is_workday =
+            |country: Country, days: &[&str]| days.iter().all(|&day| !country.is_holiday(d(day)));
+        let france = ["2024-04-01", "2024-05-09", "2024-05-20", "2024-07-14"];
+        assert!(is_holiday(Country::France, &france));
+        assert!(is_workday(Country::France, &["2024-03-29", "2024-05-10"]));
+        let germany = ["2024-03-29", "2024-10-03", "2024-12-26"];
+        assert!(is_holiday(Country::Germany, &germany));
+        assert!(is_workday(Country::Germany, &["2024-07-14"]));
+        let uk = [
+            "2021-12-27",
+            "2021-12-28",
+            "2022-01-03",
+            "2022-12-26",
+            "2022-12-27",
+            "2024-05-06",
+            "2024-05-27",
+            "2024-08-26",
+        ];
+        assert!(is_holiday(Country::UnitedKingdom, &uk));
+        let uk = ["2022-01-01", "2022-12-28", "2024-05-13", "2024-08-19"];
+        assert!(is_workday(Country::UnitedKingdom, &uk));
+        let us = [
+            "2021-12-31",
+            "2023-01-02",
+            "2024-01-15",
+            "2024-05-27",
+            "2024-07-04",
+            "2024-11-28",
+            "2026-07-03",
+        ];
+        assert!(is_holiday(Country::UnitedStates, &us));
+        let us = ["2020-06-19", "2024-01-22", "2026-07-04", "2024-11-21"];
+        assert!(is_workday(Country::UnitedStates, &us));
+
+        let mut country = None;
+        for _ in 0..5 {
+            country = Country::next(country);
+            let u = country.map_or(0, Country::as_u8);
+            assert_eq!(Country::from_u8(u), Some(country));
+        }
+        assert_eq!(country, None);
+    }
+
+    #[test]
+    fn test_sun_event() {
+        let d = |s: &str| Date::parse(s).unwrap();
+        let paris = Location {
+            latitude: 4886,
+            longitude: 235,
+            utc_offset: 120,
+        };
+        let sunrise = |date, location| sun_event(d(date), location, SunEvent::Sunrise);
+        let sunset = |date, location| sun_event(d(date), location, SunEvent::Sunset);
+        assert_eq!(sunrise("2018-06-21", paris), Some((5, 47)));
+        assert_eq!(sunset("2018-06-21", paris), Some((21, 58)));
+        let paris = Location {
+            utc_offset: 60,
+            ..paris
+        };
+        assert_eq!(sunrise("2018-12-21", paris), Some((8, 41)));
+        assert_eq!(sunset("2018-12-21", paris), Some((16, 56)));
+        let sydney = Location {
+            latitude: -3387,
+            longitude: 15121,
+            utc_offset: 600,
+        };
+        assert_eq!(sunrise("2018-06-21", sydney), Some((7, 0)));
+        assert_eq!(sunset("2018-06-21", sydney), Some((16, 54)));
+        let tromso = Location {
+            latitude: 6965,
+            longitude: 1896,
+            utc_offset: 60,
+        };
+        assert_eq!(sunrise("2018-06-21", tromso), None);
+        assert_eq!(sunset("2018-12-21", tromso), None);
+
+        assert_eq!(Location::from_words(sydney.as_words()), Some(sydney));
+        assert_eq!(Location::from_words([0, 0]), None);
+        assert_eq!(sydney.latitude().to_string(), "33.87 S");
+        assert_eq!(sydney.longitude().to_string(), "151.21 E");
+    }
 }
@@ -0,0 +1,174 @@
+use crate::alarm::Rings;
+use core::fmt;
+use heapless::{consts::*, Vec};
+
+pub type NbEvents = U16;
+
+/// How a ring ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Dismissed,
+    Snoozed,
+    /// Rang for its whole duration, or superseded by another ring.
+    Missed,
+}
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Outcome::*;
+        f.write_str(match self {
+            Dismissed => "dismissed",
+            Snoozed => "snoozed",
+            Missed => "missed",
+        })
+    }
+}
+
+/// A ring of an alarm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    /// index of the alarm, `None` for a nap
+    pub alarm: Option<u8>,
+    /// epoch of the beginning of the ring
+    pub ring: u32,
+    /// epoch of the end of the ring and how it ended, `None` while
+    /// ringing
+    pub end: Option<(u32, Outcome)>,
+}
+
+/// The last rings, the oldest being forgotten when full.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    events: Vec<Event, NbEvents>,
+    /// index of the oldest event when full
+    oldest: usize,
+}
+impl History {
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+    /// The `i`th event, 0 being the most recent.
+    pub fn get(&self, i: usize) -> Option<&Event> {
+        let len = self.len();
+        if i >= len {
+            return None;
+        }
+        self.events.get((self.oldest + len - 1 - i) % len)
+    }
+    /// The events, the most recent first.
+    pub fn iter(&self) -> impl Iterator<Item = &Event> {
+        (0..self.len()).filter_map(move |i| self.get(i))
+    }
+    fn push(&mut self, event: Event) {
+        if let Err(event) = self.events.push(event) {
+            self.events[self.oldest] = event;
+            self.oldest = (self.oldest + 1) % self.events.len();
+        }
+    }
+    /// Records the beginning of the rings at `epoch`, one event by
+    /// alarm, ending the previous rings as missed if still ringing.
+    pub fn ring(&mut self, rings: &Rings, epoch: u32) {
+        self.end(epoch, Outcome::Missed);
+        let nap = if rings.nap { Some(None) } else { None };
+        for alarm in rings.alarms().map(Some).chain(nap) {
+            self.push(Event {
+                alarm: alarm.map(|i| i as u8),
+                ring: epoch,
+                end: None,
+            });
+        }
+    }
+    /// Records the end of the current rings, if any.
+    pub fn end(&mut self, epoch: u32, outcome: Outcome) {
+        let len = self.len();
+        for i in 0..len {
+            let event = &mut self.events[(self.oldest + len - 1 - i) % len];
+            if event.end.is_some() {
+                break;
+            }
+            event.end = Some((epoch, outcome));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let mut history = History::default();
+        assert!(history.is_empty());
+        history.end(10, Outcome::Dismissed);
+        assert!(history.is_empty());
+
+        let alarm = |i: u32| Rings {
+            alarms: 1 << i,
+            nap: false,
+        };
+        history.ring(&alarm(1), 100);
+        history.end(130, Outcome::Snoozed);
+        history.end(140, Outcome::Dismissed);
+        let nap = Rings {
+            alarms: 0,
+            nap: true,
+        };
+        history.ring(&nap, 200);
+        history.ring(&alarm(2), 300);
+        assert_eq!(history.len(), 3);
+        assert_eq!(
+            history.get(0),
+            Some(&Event {
+                alarm: Some(2),
+                ring: 300,
+                end: None,
+            })
+        );
+        assert_eq!(history.get(1).unwrap().end, Some((300, Outcome::Missed)));
+        assert_eq!(history.get(2).unwrap().end, Some((130, Outcome::Snoozed)));
+        assert_eq!(history.get(3), None);
+
+        for i in 0..20 {
+            history.ring(&alarm(0), 1000 + i);
+        }
+        assert_eq!(history.len(), 16);
+        let rings: Vec<u32, NbEvents> = history.iter().map(|e| e.ring).collect();
+        assert_eq!(rings[0], 1019);
+        assert_eq!(rings[15], 1004);
+        history.end(2000, Outcome::Dismissed);
+        assert_eq!(
+            history.get(0).unwrap().end,
+            Some((2000, Outcome::Dismissed))
+        );
+        assert_eq!(history.get(1).unwrap().end, Some((1019, Outcome::Missed)));
+    }
+
+    #[test]
+    fn test_simultaneous_rings() {
+        let mut history = History::default();
+        history.ring(
+            &Rings {
+                alarms: 0b101,
+                nap: true,
+            },
+            100,
+        );
+        let alarms: Vec<_, U4> = history.iter().map(|e| e.alarm).collect();
+        assert_eq!(alarms, &[None, Some(2), Some(0)]);
+        history.end(130, Outcome::Dismissed);
+        assert!(history
+            .iter()
+            .all(|e| e.end == Some((130, Outcome::Dismissed))));
+        history.ring(
+            &Rings {
+                alarms: 2,
+                nap: false,
+            },
+            200,
+        );
+        assert_eq!(history.len(), 4);
+        assert_eq!(history.get(1).unwrap().end, Some((130, Outcome::Dismissed)));
+    }
+}
@@ -5,5 +5,9 @@ extern crate std;
 
 pub mod alarm;
 pub mod button;
+pub mod chime;
 pub mod datetime;
+pub mod history;
+pub mod sleep;
+pub mod store;
 pub mod ui;
@@ -0,0 +1,73 @@
+/// Duration of a sleep cycle, unit: min.
+pub const CYCLE_MIN: u32 = 90;
+/// Time needed to fall asleep, unit: min.
+pub const FALL_ASLEEP_MIN: u32 = 15;
+/// Numbers of sleep cycles of the suggested bedtimes.
+pub const SUGGESTED_CYCLES: [u8; 4] = [6, 5, 4, 3];
+/// Longest sleep target, unit: min.
+pub const MAX_TARGET: u16 = 12 * 60;
+
+/// The wanted sleep duration before the next alarm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SleepConfig {
+    /// unit: min
+    pub target: u16,
+    /// chime a reminder at bedtime
+    pub reminder: bool,
+}
+impl Default for SleepConfig {
+    fn default() -> Self {
+        Self {
+            target: 8 * 60,
+            reminder: false,
+        }
+    }
+}
+impl SleepConfig {
+    /// The bedtime (epoch) for a ring at `ring` (epoch).
+    pub fn bedtime(&self, ring: u32) -> u32 {
+        ring.saturating_sub(u32::from(self.target) * 60)
+    }
+    pub fn as_u32(&self) -> u32 {
+        u32::from(self.target) | (self.reminder as u32) << 16 | 1 << 24
+    }
+    pub fn try_from(u: u32) -> Option<Self> {
+        let target = u as u16;
+        if u >> 24 != 1 || target > MAX_TARGET {
+            return None;
+        }
+        Some(Self {
+            target,
+            reminder: u & 1 << 16 != 0,
+        })
+    }
+}
+
+/// The bedtime (epoch) to wake up at `ring` (epoch) after
+/// `nb_cycles` sleep cycles.
+pub fn cycle_bedtime(ring: u32, nb_cycles: u8) -> u32 {
+    let min = FALL_ASLEEP_MIN + u32::from(nb_cycles) * CYCLE_MIN;
+    ring.saturating_sub(min * 60)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sleep() {
+        let ring = 1_539_588_300;
+        let config = SleepConfig {
+            target: 7 * 60 + 30,
+            reminder: true,
+        };
+        assert_eq!(config.bedtime(ring), ring - 27_000);
+        assert_eq!(Some(config), SleepConfig::try_from(config.as_u32()));
+        assert_eq!(None, SleepConfig::try_from(0));
+        assert_eq!(None, SleepConfig::try_from((1 << 24) | (13 * 60)));
+
+        // 7:25 ring, 6 cycles after falling asleep: 22:10
+        assert_eq!(cycle_bedtime(ring, 6), ring - (9 * 3600 + 15 * 60));
+        assert_eq!(cycle_bedtime(ring, 3), ring - (4 * 3600 + 45 * 60));
+    }
+}
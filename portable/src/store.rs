@@ -0,0 +1,426 @@
+use core::fmt;
+
+/// Value of an erased half-word.
+pub const ERASED: u16 = 0xffff;
+/// Marks a bank with a complete copy of the records.
+const MAGIC: u16 = 0x5e77;
+/// Half-words of the header of a bank: its generation then `MAGIC`.
+const HEADER_LEN: usize = 2;
+/// Half-words of a record: the low and high halves of the value,
+/// then the key, written last to mark the record as complete.
+const RECORD_LEN: usize = 3;
+
+/// Two banks of memory that can each be erased as a whole and where
+/// erased half-words can be written once, as flash pages.
+pub trait Flash {
+    /// Number of half-words of a bank.
+    fn bank_len(&self) -> usize;
+    fn read(&self, bank: usize, index: usize) -> u16;
+    /// Writes a previously erased half-word, `Err(Error::Flash)` if
+    /// it fails.
+    fn write(&mut self, bank: usize, index: usize, value: u16) -> Result<(), Error>;
+    /// Erases the whole bank, every half-word reading `ERASED`
+    /// afterward, `Err(Error::Flash)` if it fails.
+    fn erase(&mut self, bank: usize) -> Result<(), Error>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// the records don't fit in a bank
+    Full,
+    /// a write or an erase of the flash failed
+    Flash,
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Error::Full => "storage full",
+            Error::Flash => "flash error",
+        })
+    }
+}
+
+/// Key/value store appending its records to a bank of a `Flash`. The
+/// last record of a key gives its value, a key without record having
+/// the value 0.
+///
+/// When the bank is full, the last non zero record of each key is
+/// copied to the other bank, that becomes the active one only when the copy is
+/// complete: an interrupted or failed write loses at most the record
+/// being written.
+pub struct Store<F> {
+    flash: F,
+    /// the active bank
+    bank: usize,
+    /// index of the first free half-word of the active bank, 0 if
+    /// no bank has a header yet
+    end: usize,
+}
+impl<F: Flash> Store<F> {
+    pub fn new(flash: F) -> Self {
+        let generation = |bank| Self::generation(&flash, bank);
+        let bank = match (generation(0), generation(1)) {
+            (Some(g0), Some(g1)) if (g1.wrapping_sub(g0) as i16) > 0 => 1,
+            (None, Some(_)) => 1,
+            _ => 0,
+        };
+        let mut store = Self {
+            flash,
+            bank,
+            end: 0,
+        };
+        if Self::generation(&store.flash, bank).is_some() {
+            store.end = HEADER_LEN;
+            while store.end + RECORD_LEN <= store.flash.bank_len()
+                && (0..RECORD_LEN).any(|i| store.read(store.end + i) != ERASED)
+            {
+                store.end += RECORD_LEN;
+            }
+        }
+        store
+    }
+    /// Maximum number of records of the store.
+    pub fn capacity(&self) -> usize {
+        (self.flash.bank_len() - HEADER_LEN) / RECORD_LEN
+    }
+    pub fn is_empty(&self) -> bool {
+        self.records().next().is_none()
+    }
+    /// The value of `key`, `None` if it has no record.
+    pub fn get(&self, key: u16) -> Option<u32> {
+        self.records()
+            .filter(|&(k, _)| k == key)
+            .last()
+            .map(|(_, v)| v)
+    }
+    /// The complete records, the oldest first.
+    pub fn records<'a>(&'a self) -> impl Iterator<Item = (u16, u32)> + 'a {
+        (HEADER_LEN..self.end)
+            .step_by(RECORD_LEN)
+            .filter_map(move |i| self.record(i))
+    }
+    pub fn set(&mut self, key: u16, value: u32) -> Result<(), Error> {
+        if self.end == 0 {
+            // never used, or only by an unknown format
+            self.flash.erase(self.bank)?;
+            self.write_header(self.bank, 0)?;
+            self.end = HEADER_LEN;
+        }
+        if self.end + RECORD_LEN > self.flash.bank_len() {
+            return Err(Error::Full);
+        }
+        let res = Self::write_record(&mut self.flash, self.bank, self.end, key, value);
+        // a partially written record is skipped, but an erased one
+        // would end the records when reloaded
+        if res.is_ok() || (0..RECORD_LEN).any(|i| self.read(self.end + i) != ERASED) {
+            self.end += RECORD_LEN;
+        }
+        res
+    }
+    /// Saves the records, appending only the modified ones. When the
+    /// bank is full, the non zero records are written to the other
+    /// bank, with the last non zero value of the keys missing from
+    /// `records`. Returns `Err(Error::Full)`, without any
+    /// modification, if they don't fit in a bank.
+    pub fn save<I>(&mut self, records: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = (u16, u32)> + Clone,
+    {
+        for (key, value) in records.clone() {
+            if self.get(key).unwrap_or(0) == value {
+                continue;
+            }
+            match self.set(key, value) {
+                Ok(()) => {}
+                Err(Error::Full) => return self.compact(records),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+    /// Writes the non zero records, then the kept ones, to the other
+    /// bank, making it the active one.
+    fn compact<I>(&mut self, records: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = (u16, u32)> + Clone,
+    {
+        let non_zero = |&(_, v): &(u16, u32)| v != 0;
+        let indices = (HEADER_LEN..self.end).step_by(RECORD_LEN);
+        let nb_kept = indices
+            .clone()
+            .filter(|&i| self.kept(i, &records).is_some())
+            .count();
+        if records.clone().into_iter().filter(non_zero).count() + nb_kept > self.capacity() {
+            return Err(Error::Full);
+        }
+        let bank = 1 - self.bank;
+        self.flash.erase(bank)?;
+        let mut end = HEADER_LEN;
+        for (key, value) in records.clone().into_iter().filter(non_zero) {
+            Self::write_record(&mut self.flash, bank, end, key, value)?;
+            end += RECORD_LEN;
+        }
+        for i in indices {
+            if let Some((key, value)) = self.kept(i, &records) {
+                Self::write_record(&mut self.flash, bank, end, key, value)?;
+                end += RECORD_LEN;
+            }
+        }
+        let generation = self.read(0).wrapping_add(1);
+        self.write_header(bank, generation)?;
+        self.bank = bank;
+        self.end = end;
+        Ok(())
+    }
+    /// The generation of a bank with a complete copy of the records.
+    fn generation(flash: &F, bank: usize) -> Option<u16> {
+        match flash.read(bank, 1) {
+            MAGIC => Some(flash.read(bank, 0)),
+            _ => None,
+        }
+    }
+    /// The record at `index` of the active bank, `None` if
+    /// incomplete.
+    fn record(&self, index: usize) -> Option<(u16, u32)> {
+        let key = self.read(index + 2);
+        if key == ERASED {
+            return None;
+        }
+        let value = u32::from(self.read(index)) | (u32::from(self.read(index + 1)) << 16);
+        Some((key, value))
+    }
+    /// The record at `index` if it must be kept by a compaction
+    /// saving `records`: the last record of a key missing from
+    /// `records`, with a non zero value.
+    fn kept<I>(&self, index: usize, records: &I) -> Option<(u16, u32)>
+    where
+        I: IntoIterator<Item = (u16, u32)> + Clone,
+    {
+        let (key, value) = self.record(index)?;
+        let is_last = || {
+            (index + RECORD_LEN..self.end)
+                .step_by(RECORD_LEN)
+                .filter_map(|i| self.record(i))
+                .all(|(k, _)| k != key)
+        };
+        let is_saved = || records.clone().into_iter().any(|(k, _)| k == key);
+        if value != 0 && is_last() && !is_saved() {
+            Some((key, value))
+        } else {
+            None
+        }
+    }
+    fn read(&self, index: usize) -> u16 {
+        self.flash.read(self.bank, index)
+    }
+    fn write_header(&mut self, bank: usize, generation: u16) -> Result<(), Error> {
+        self.flash.write(bank, 0, generation)?;
+        self.flash.write(bank, 1, MAGIC)
+    }
+    fn write_record(
+        flash: &mut F,
+        bank: usize,
+        index: usize,
+        key: u16,
+        value: u32,
+    ) -> Result<(), Error> {
+        flash.write(bank, index, value as u16)?;
+        flash.write(bank, index + 1, (value >> 16) as u16)?;
+        flash.write(bank, index + 2, key)
+    }
+}
+
+/// A `Flash` in RAM, for the tests.
+#[cfg(test)]
+pub struct RamFlash {
+    pub banks: [std::vec::Vec<u16>; 2],
+    /// number of writes and erases succeeding before all fail
+    pub failing_after: Option<usize>,
+}
+#[cfg(test)]
+impl RamFlash {
+    /// Two erased banks of `len` half-words.
+    pub fn new(len: usize) -> Self {
+        Self {
+            banks: [std::vec![ERASED; len], std::vec![ERASED; len]],
+            failing_after: None,
+        }
+    }
+    fn operate(&mut self) -> Result<(), Error> {
+        match &mut self.failing_after {
+            Some(0) => Err(Error::Flash),
+            Some(n) => {
+                *n -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+#[cfg(test)]
+impl Flash for RamFlash {
+    fn bank_len(&self) -> usize {
+        self.banks[0].len()
+    }
+    fn read(&self, bank: usize, index: usize) -> u16 {
+        self.banks[bank][index]
+    }
+    fn write(&mut self, bank: usize, index: usize, value: u16) -> Result<(), Error> {
+        self.operate()?;
+        assert_eq!(self.banks[bank][index], ERASED);
+        self.banks[bank][index] = value;
+        Ok(())
+    }
+    fn erase(&mut self, bank: usize) -> Result<(), Error> {
+        self.operate()?;
+        self.banks[bank].iter_mut().for_each(|h| *h = ERASED);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_store() {
+        let mut store = Store::new(RamFlash::new(32));
+        assert!(store.is_empty());
+        assert_eq!(store.get(0), None);
+
+        assert_eq!(store.save([(0, 42), (1, 0)].iter().cloned()), Ok(()));
+        assert_eq!(store.get(1), None);
+        assert_eq!(store.save([(0, 42), (1, 1)].iter().cloned()), Ok(()));
+        assert_eq!(store.get(0), Some(42));
+        assert_eq!(store.get(1), Some(1));
+        assert_eq!(store.get(2), None);
+        assert_eq!(store.records().count(), 2);
+
+        let store = Store::new(store.flash);
+        assert!(!store.is_empty());
+        assert_eq!(store.get(0), Some(42));
+        assert_eq!(store.get(1), Some(1));
+        assert_eq!(store.records().count(), 2);
+
+        let mut store = store;
+        assert_eq!(store.save([(0, 42), (1, 0)].iter().cloned()), Ok(()));
+        assert_eq!(store.get(1), Some(0));
+        assert_eq!(store.set(2, !0), Ok(()));
+        assert_eq!(store.get(2), Some(!0));
+    }
+
+    #[test]
+    fn test_store_full() {
+        // 10 records by bank
+        let mut store = Store::new(RamFlash::new(32));
+        assert_eq!(store.capacity(), 10);
+        for i in 0..10 {
+            assert_eq!(store.set(0, i), Ok(()));
+        }
+        assert_eq!(store.set(0, 10), Err(Error::Full));
+        assert_eq!(store.get(0), Some(9));
+
+        assert_eq!(
+            store.save([(0, 10), (1, 1), (2, 0)].iter().cloned()),
+            Ok(())
+        );
+        assert_eq!(store.records().count(), 2);
+        assert_eq!(store.get(0), Some(10));
+        assert_eq!(store.get(1), Some(1));
+        assert_eq!(store.get(2), None);
+        let store = Store::new(store.flash);
+        assert_eq!(store.get(0), Some(10));
+        assert_eq!(store.records().count(), 2);
+
+        // too many records: nothing is lost
+        let mut store = store;
+        for i in 2..10 {
+            assert_eq!(store.set(i, 1), Ok(()));
+        }
+        let records: std::vec::Vec<_> = (0..11).map(|k| (k, 2)).collect();
+        assert_eq!(store.save(records.iter().cloned()), Err(Error::Full));
+        assert_eq!(store.get(0), Some(10));
+        assert_eq!(store.get(9), Some(1));
+        assert_eq!(store.get(10), None);
+
+        // the keys missing from the saved records are kept
+        let mut store = Store::new(RamFlash::new(32));
+        for i in 0..10 {
+            assert_eq!(store.set(i as u16 % 4, i), Ok(()));
+        }
+        assert_eq!(store.save([(0, 1)].iter().cloned()), Ok(()));
+        assert_eq!(store.records().count(), 4);
+        assert_eq!(store.get(0), Some(1));
+        assert_eq!(store.get(1), Some(9));
+        assert_eq!(store.get(2), Some(6));
+        assert_eq!(store.get(3), Some(7));
+        for i in 4..10 {
+            assert_eq!(store.set(i, 1), Ok(()));
+        }
+        assert_eq!(store.save([(10, 1)].iter().cloned()), Err(Error::Full));
+        assert_eq!(store.get(10), None);
+        assert_eq!(store.get(3), Some(7));
+    }
+
+    #[test]
+    fn test_store_interrupted() {
+        let mut store = Store::new(RamFlash::new(32));
+        for i in 0..10 {
+            assert_eq!(store.set(i, i.into()), Ok(()));
+        }
+        let mut flash = store.flash;
+
+        // a compaction interrupted before the header of the new bank
+        flash.banks[1][HEADER_LEN] = 0;
+        let mut store = Store::new(flash);
+        assert_eq!(store.get(9), Some(9));
+        // the keys from 5 are removed
+        let records: std::vec::Vec<_> = (0..10).map(|k| (k, u32::from(k < 5) * 2)).collect();
+        assert_eq!(store.save(records.iter().cloned()), Ok(()));
+        let mut flash = store.flash;
+        assert_eq!(flash.banks[1][1], MAGIC);
+
+        // a record interrupted before its key
+        let end = HEADER_LEN + 5 * RECORD_LEN;
+        flash.banks[1][end] = 3;
+        let mut store = Store::new(flash);
+        assert_eq!(store.records().count(), 5);
+        assert_eq!(store.get(9), None);
+        assert_eq!(store.set(0, 4), Ok(()));
+        let store = Store::new(store.flash);
+        assert_eq!(store.get(0), Some(4));
+        assert_eq!(store.records().count(), 6);
+    }
+
+    #[test]
+    fn test_store_failing() {
+        let mut store = Store::new(RamFlash::new(32));
+        for i in 0..10 {
+            assert_eq!(store.set(i, 1), Ok(()));
+        }
+
+        // a failed compaction keeps the active bank
+        store.flash.failing_after = Some(4);
+        // the keys from 5 are removed
+        let records: std::vec::Vec<_> = (0..10).map(|k| (k, u32::from(k < 5) * 2)).collect();
+        assert_eq!(store.save(records.iter().cloned()), Err(Error::Flash));
+        assert_eq!(store.get(0), Some(1));
+        let mut store = Store::new(store.flash);
+        assert_eq!(store.get(0), Some(1));
+        assert_eq!(store.records().count(), 10);
+
+        // the save is done again once the flash works
+        store.flash.failing_after = None;
+        assert_eq!(store.save(records.iter().cloned()), Ok(()));
+        assert_eq!(store.get(0), Some(2));
+
+        // a failed record doesn't end the records
+        store.flash.failing_after = Some(0);
+        assert_eq!(store.set(5, 3), Err(Error::Flash));
+        store.flash.failing_after = None;
+        assert_eq!(store.set(6, 3), Ok(()));
+        let store = Store::new(store.flash);
+        assert_eq!(store.get(5), None);
+        assert_eq!(store.get(6), Some(3));
+    }
+}
@@ -0,0 +1,256 @@
+use super::menu;
+use crate::alarm;
+use core::fmt::Write;
+use epd_waveshare::epd2in9::Display2in9;
+use heapless::{consts::*, String};
+
+/// Duration cancel must be held, unit: s.
+const HOLD_SEC: u32 = 3;
+const SEQUENCE_LEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Cancel,
+    Minus,
+    Plus,
+    Ok,
+}
+impl Button {
+    fn from_u32(u: u32) -> Self {
+        match u % 4 {
+            0 => Button::Cancel,
+            1 => Button::Minus,
+            2 => Button::Plus,
+            _ => Button::Ok,
+        }
+    }
+    fn as_str(self) -> &'static str {
+        match self {
+            Button::Cancel => "Cancel",
+            Button::Minus => "-",
+            Button::Plus => "+",
+            Button::Ok => "OK",
+        }
+    }
+}
+
+/// A challenge being solved to dismiss the ringing alarm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Challenge {
+    /// The epoch when cancel was pressed, `None` if released.
+    Hold(Option<u32>),
+    /// The buttons to press, and the number of buttons already
+    /// pressed.
+    Sequence([Button; SEQUENCE_LEN], usize),
+    /// The answer to `a + b` is edited digit by digit, `digit` being
+    /// the index of the edited digit, 0 for the tens.
+    Arithmetic {
+        a: u8,
+        b: u8,
+        answer: [u8; 2],
+        digit: usize,
+    },
+}
+impl Challenge {
+    /// Begins a challenge, cancel being pressed at `now` (epoch).
+    /// `seed` randomizes the challenge. Returns `None` if there is
+    /// nothing to solve.
+    pub fn new(challenge: alarm::Challenge, now: u32, seed: u32) -> Option<Self> {
+        let mut rng = Rng(seed | 1);
+        Some(match challenge {
+            alarm::Challenge::Press => return None,
+            alarm::Challenge::Hold => Challenge::Hold(Some(now)),
+            alarm::Challenge::Sequence => {
+                let mut buttons = [Button::Cancel; SEQUENCE_LEN];
+                for b in &mut buttons {
+                    *b = Button::from_u32(rng.next());
+                }
+                Challenge::Sequence(buttons, 0)
+            }
+            alarm::Challenge::Arithmetic => Challenge::new_arithmetic(&mut rng),
+        })
+    }
+    fn new_arithmetic(rng: &mut Rng) -> Self {
+        Challenge::Arithmetic {
+            a: 10 + (rng.next() % 40) as u8,
+            b: 10 + (rng.next() % 40) as u8,
+            answer: [0, 0],
+            digit: 0,
+        }
+    }
+    /// Handles a button pressed at `now` (epoch). Returns true if the
+    /// challenge is solved.
+    pub fn press(&mut self, button: Button, now: u32) -> bool {
+        match self {
+            Challenge::Hold(pressed) => {
+                if button == Button::Cancel {
+                    *pressed = Some(now);
+                }
+                false
+            }
+            Challenge::Sequence(buttons, done) => {
+                if buttons[*done] == button {
+                    *done += 1;
+                } else {
+                    // a mistake restarts the sequence
+                    *done = if buttons[0] == button { 1 } else { 0 };
+                }
+                *done == SEQUENCE_LEN
+            }
+            Challenge::Arithmetic {
+                a,
+                b,
+                answer,
+                digit,
+            } => match button {
+                Button::Plus => {
+                    answer[*digit] = (answer[*digit] + 1) % 10;
+                    false
+                }
+                Button::Minus => {
+                    answer[*digit] = (answer[*digit] + 9) % 10;
+                    false
+                }
+                Button::Cancel => {
+                    *digit = 0;
+                    false
+                }
+                Button::Ok if *digit == 0 => {
+                    *digit = 1;
+                    false
+                }
+                Button::Ok => {
+                    let answer = answer[0] * 10 + answer[1];
+                    if answer == *a + *b {
+                        return true;
+                    }
+                    // a wrong answer gives a new addition
+                    *self = Challenge::new_arithmetic(&mut Rng(now ^ u32::from(answer) | 1));
+                    false
+                }
+            },
+        }
+    }
+    /// Handles the release of cancel.
+    pub fn release(&mut self) {
+        if let Challenge::Hold(pressed) = self {
+            *pressed = None;
+        }
+    }
+    /// Returns true if the challenge is solved at `now` (epoch).
+    pub fn is_solved_at(&self, now: u32) -> bool {
+        match *self {
+            // pressed during the second `pressed`, thus held at least
+            // `HOLD_SEC` after `HOLD_SEC + 1` seconds
+            Challenge::Hold(Some(pressed)) => now > pressed + HOLD_SEC,
+            _ => false,
+        }
+    }
+    pub fn render(&self, display: &mut Display2in9) {
+        let mut s = String::<U40>::new();
+        match self {
+            Challenge::Hold(None) => {
+                write!(s, "Hold cancel for {} s", HOLD_SEC).unwrap();
+                menu::render("Dismiss:", &[&s], 0, display);
+            }
+            Challenge::Hold(Some(_)) => {
+                menu::render("Dismiss:", &["Keep holding cancel..."], 0, display);
+            }
+            Challenge::Sequence(buttons, done) => {
+                s.push_str("Press:").unwrap();
+                for (i, b) in buttons.iter().enumerate() {
+                    let b = if i < *done { "*" } else { b.as_str() };
+                    write!(s, " {}", b).unwrap();
+                }
+                menu::render("Dismiss:", &[&s], 0, display);
+            }
+            Challenge::Arithmetic {
+                a,
+                b,
+                answer,
+                digit,
+            } => {
+                write!(s, "{} + {} = ", a, b).unwrap();
+                for (i, d) in answer.iter().enumerate() {
+                    if i == *digit {
+                        write!(s, "[{}]", d).unwrap();
+                    } else {
+                        write!(s, "{}", d).unwrap();
+                    }
+                }
+                menu::render("Dismiss:", &[&s], 0, display);
+            }
+        }
+    }
+}
+
+/// xorshift pseudo random number generator, the state must not be 0.
+struct Rng(u32);
+impl Rng {
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hold() {
+        let mut c = Challenge::new(alarm::Challenge::Hold, 100, 42).unwrap();
+        assert!(!c.is_solved_at(103));
+        c.release();
+        assert!(!c.is_solved_at(110));
+        assert!(!c.press(Button::Cancel, 110));
+        assert!(c.is_solved_at(114));
+        assert_eq!(Challenge::new(alarm::Challenge::Press, 100, 42), None);
+    }
+
+    #[test]
+    fn test_sequence() {
+        let mut c = Challenge::new(alarm::Challenge::Sequence, 100, 42).unwrap();
+        let buttons = match c {
+            Challenge::Sequence(buttons, 0) => buttons,
+            _ => panic!(),
+        };
+        let wrong = (0..4)
+            .map(Button::from_u32)
+            .find(|&b| b != buttons[0] && b != buttons[1])
+            .unwrap();
+        assert!(!c.press(buttons[0], 100));
+        assert!(!c.press(wrong, 100));
+        for (i, &b) in buttons.iter().enumerate() {
+            assert_eq!(c.press(b, 101), i + 1 == SEQUENCE_LEN);
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let mut c = Challenge::new(alarm::Challenge::Arithmetic, 100, 42).unwrap();
+        let sum = match c {
+            Challenge::Arithmetic { a, b, .. } => a + b,
+            _ => panic!(),
+        };
+        assert!((20..100).contains(&sum));
+        let enter = |c: &mut Challenge, n: u8| {
+            for _ in 0..n / 10 {
+                assert!(!c.press(Button::Plus, 100));
+            }
+            assert!(!c.press(Button::Ok, 100));
+            for _ in 0..10 - n % 10 {
+                assert!(!c.press(Button::Minus, 100));
+            }
+            c.press(Button::Ok, 100)
+        };
+        assert!(!enter(&mut c, sum + 1));
+        let sum = match c {
+            Challenge::Arithmetic { a, b, digit: 0, .. } => a + b,
+            _ => panic!(),
+        };
+        assert!(enter(&mut c, sum));
+    }
+}
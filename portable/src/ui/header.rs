@@ -8,6 +8,8 @@ const MARGIN: i32 = 0;
 const FONT_HEIGHT: i32 = 16;
 const FONT_WIDTH: i32 = 8;
 const BOTTOM_Y: i32 = 128 - MARGIN - FONT_HEIGHT;
+/// Number of characters in a line.
+pub const NB_CHARS: usize = ((296 - 2 * MARGIN) / FONT_WIDTH) as usize;
 
 pub struct Header<'a> {
     display: &'a mut Display2in9,
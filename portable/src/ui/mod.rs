@@ -1,5 +1,12 @@
-use crate::alarm::{Alarm, AlarmManager};
+use crate::alarm::{
+    self, Alarm, AlarmManager, Exception, Label, NbAlarms, Rings, Ringtone, NB_PROFILES,
+};
+use crate::chime::ChimeConfig;
 use crate::datetime;
+use crate::history::{self, History, NbEvents};
+use crate::sleep::{self, SleepConfig};
+use crate::store;
+use core::cmp::max;
 use core::fmt::{self, Write};
 use embedded_graphics::coord::Coord;
 use embedded_graphics::fonts::Font8x16;
@@ -9,6 +16,7 @@ use epd_waveshare::graphics::Display;
 use epd_waveshare::prelude::{Color, DisplayRotation};
 use heapless::{consts::*, String, Vec};
 
+mod challenge;
 mod header;
 mod menu;
 mod seven_segments;
@@ -23,7 +31,14 @@ pub enum Msg {
     ButtonMinus,
     ButtonPlus,
     ButtonOk,
-    AlarmManager(AlarmManager),
+    ButtonCancelReleased,
+    /// The alarm manager has been modified.
+    AlarmManagerUpdated,
+    /// The alarms beginning to ring, sent after
+    /// `AlarmManagerUpdated`.
+    AlarmRinging(Rings),
+    /// The result of the last save of the settings.
+    Saved(Result<(), store::Error>),
 }
 impl Msg {
     fn is_button(&self) -> bool {
@@ -39,6 +54,27 @@ impl Msg {
 pub enum Cmd {
     UpdateRtc(datetime::DateTime),
     UpdateAlarm(Alarm, usize),
+    PreviewRingtone(Ringtone),
+    AcknowledgeMissed,
+    UpdateSleepConfig(SleepConfig),
+    UpdateChimeConfig(ChimeConfig),
+    /// Suspends the alarms until the end of the given date, `None`
+    /// resuming them.
+    Suspend(Option<datetime::Date>),
+    AddException(Exception),
+    /// Removes the exception of the given index.
+    RemoveException(usize),
+    ActivateProfile(u8),
+    RenameProfile(u8, Label),
+    UpdateLocation(datetime::Location),
+    /// Sets the nap to ring in the given minutes, 0 to cancel it.
+    Nap(u8),
+    /// The ringing alarm must be snoozed.
+    Snooze,
+    /// The ringing alarm must be dismissed.
+    Dismiss,
+    /// Stops the sound, unless an alarm is ringing.
+    StopSound,
     FullUpdate,
 }
 
@@ -48,8 +84,13 @@ pub struct Model {
     last_input: u32,
     env: Environment,
     nb_fail_environment: u32,
-    alarm_manager: AlarmManager,
+    /// the last save failed, the settings being lost at the next
+    /// reset
+    save_error: Option<store::Error>,
     screen: state::Screen,
+    /// the screen replaced by the ringing alarm, displayed again
+    /// when the ring ends
+    interrupted: Option<state::Screen>,
 }
 
 impl Model {
@@ -59,13 +100,15 @@ impl Model {
             last_input: 0,
             env: Default::default(),
             nb_fail_environment: 0,
-            alarm_manager: AlarmManager::default(),
+            save_error: None,
             screen: state::Screen::Clock,
+            interrupted: None,
         }
     }
-    pub fn update(&mut self, msg: Msg) -> Vec<Cmd, U4> {
+    pub fn update(&mut self, msg: Msg, manager: &AlarmManager, history: &History) -> Vec<Cmd, U4> {
         use self::state::Screen::*;
         let mut cmds = Vec::new();
+        let previous_input = self.last_input;
 
         if msg.is_button() {
             self.update_last_input();
@@ -74,7 +117,16 @@ impl Model {
         match msg {
             Msg::DateTime(dt) => {
                 self.now = dt;
+                let now = self.now.to_epoch().unwrap_or(0);
+                if let Challenge(c) = &self.screen {
+                    if c.is_solved_at(now) {
+                        self.screen = self.solved(&mut cmds);
+                    }
+                }
+                // a ringing alarm is not abandoned, it rings until
+                // dismissed, snoozed or missed
                 if self.screen != state::Screen::Clock
+                    && !matches!(self.screen, Ringing(_) | Challenge(_))
                     && self
                         .now
                         .to_epoch()
@@ -91,7 +143,24 @@ impl Model {
                 self.nb_fail_environment = 0;
             }
             Msg::FailEnvironment => self.nb_fail_environment += 1,
-            Msg::AlarmManager(am) => self.alarm_manager = am,
+            Msg::Saved(res) => self.save_error = res.err(),
+            Msg::AlarmManagerUpdated => {
+                if manager.ringing().is_none() && matches!(self.screen, Ringing(_) | Challenge(_)) {
+                    self.screen = self.ring_ended();
+                    cmds.push(Cmd::FullUpdate).unwrap();
+                }
+            }
+            Msg::AlarmRinging(rings) => {
+                let screen = ::core::mem::replace(&mut self.screen, Ringing(rings));
+                if !matches!(screen, Ringing(_) | Challenge(_)) {
+                    self.interrupted = Some(screen);
+                }
+            }
+            Msg::ButtonCancelReleased => {
+                if let Challenge(c) = &mut self.screen {
+                    c.release();
+                }
+            }
             Msg::ButtonOk => {
                 use self::state::{EditDateTime, MenuElt};
                 self.screen = match ::core::mem::replace(&mut self.screen, Clock) {
@@ -103,6 +172,88 @@ impl Model {
                         SetClock(EditDateTime::new(dt))
                     }
                     Menu(MenuElt::ManageAlarms) => ManageAlarms(0),
+                    Menu(MenuElt::Nap) => SetNap(state::DEFAULT_NAP),
+                    Menu(MenuElt::Profiles) => Profiles(usize::from(manager.active_profile)),
+                    Profiles(i) if i < NB_PROFILES => {
+                        cmds.push(Cmd::ActivateProfile(i as u8)).unwrap();
+                        Profiles(i)
+                    }
+                    Profiles(i) => {
+                        let p = i - NB_PROFILES;
+                        RenameProfile(p, manager.profiles[p], 0)
+                    }
+                    RenameProfile(p, name, i) => state::rename_profile_ok(p, name, i, &mut cmds),
+                    Menu(MenuElt::Vacation) => {
+                        let today = self.now.date();
+                        let until = manager.suspended_until;
+                        SetVacation(until.filter(|&until| until >= today))
+                    }
+                    SetVacation(until) => {
+                        cmds.push(Cmd::Suspend(until)).unwrap();
+                        Clock
+                    }
+                    Menu(MenuElt::Exceptions) => Exceptions(0),
+                    Exceptions(i) if i < manager.exceptions.len() => {
+                        cmds.push(Cmd::RemoveException(i)).unwrap();
+                        Exceptions(i)
+                    }
+                    Exceptions(i) if manager.exceptions.len() == manager.exceptions.capacity() => {
+                        Exceptions(i)
+                    }
+                    Exceptions(_) => {
+                        let today = self.now.date();
+                        SetException(Exception::new(today, today), 0)
+                    }
+                    SetException(e, i) if i + 1 < state::NB_EXCEPTION_FIELDS => {
+                        SetException(e, i + 1)
+                    }
+                    SetException(e, _) => {
+                        cmds.push(Cmd::AddException(e)).unwrap();
+                        Exceptions(manager.exceptions.len())
+                    }
+                    Menu(MenuElt::History) => History(0),
+                    Menu(MenuElt::Sleep) => Sleep(0),
+                    Sleep(0) => {
+                        let mut config = manager.sleep_config;
+                        config.reminder = !config.reminder;
+                        cmds.push(Cmd::UpdateSleepConfig(config)).unwrap();
+                        Sleep(0)
+                    }
+                    Sleep(1) => SetSleepTarget(manager.sleep_config),
+                    Sleep(i) => Sleep(i),
+                    Menu(MenuElt::Chime) => Chime(0),
+                    Chime(i @ 0) | Chime(i @ 1) => {
+                        let mut config = manager.chime_config;
+                        if i == 0 {
+                            config.kind.next();
+                        } else {
+                            config.quarters = !config.quarters;
+                        }
+                        cmds.push(Cmd::UpdateChimeConfig(config)).unwrap();
+                        Chime(i)
+                    }
+                    Chime(i) => SetQuietHour(manager.chime_config, i),
+                    SetQuietHour(config, i) => {
+                        cmds.push(Cmd::UpdateChimeConfig(config)).unwrap();
+                        Chime(i)
+                    }
+                    Menu(MenuElt::Location) => SetLocation(manager.location.unwrap_or_default(), 0),
+                    SetLocation(location, i) if i + 1 < state::NB_LOCATION_FIELDS => {
+                        SetLocation(location, i + 1)
+                    }
+                    SetLocation(location, _) => {
+                        cmds.push(Cmd::UpdateLocation(location)).unwrap();
+                        Menu(MenuElt::Location)
+                    }
+                    SetSleepTarget(config) => {
+                        cmds.push(Cmd::UpdateSleepConfig(config)).unwrap();
+                        Sleep(1)
+                    }
+                    History(_) => Menu(MenuElt::History),
+                    SetNap(minutes) => {
+                        cmds.push(Cmd::Nap(minutes)).unwrap();
+                        Clock
+                    }
                     SetClock(mut edit) => {
                         if let Some(dt) = edit.ok() {
                             cmds.push(Cmd::UpdateRtc(dt)).unwrap();
@@ -111,20 +262,57 @@ impl Model {
                             SetClock(edit)
                         }
                     }
-                    ManageAlarms(i) => ManageAlarm(state::ManageAlarm::new(&self.alarm_manager, i)),
-                    ManageAlarm(state) => state.ok(&mut cmds),
+                    ManageAlarms(i) => {
+                        ManageAlarm(state::ManageAlarm::new(manager, i, self.now.date()))
+                    }
+                    ManageAlarm(state) => state.ok(manager, &mut cmds),
+                    Ringing(_) => {
+                        cmds.push(Cmd::Snooze).unwrap();
+                        self.ring_ended()
+                    }
+                    Challenge(c) => self.press(c, challenge::Button::Ok, &mut cmds),
                 };
                 if let Clock = self.screen {
                     cmds.push(Cmd::FullUpdate).unwrap();
                 }
             }
             Msg::ButtonCancel => {
+                if self.screen == Clock && manager.missed().is_some() {
+                    cmds.push(Cmd::AcknowledgeMissed).unwrap();
+                }
+                if !matches!(self.screen, Ringing(_) | Challenge(_)) {
+                    cmds.push(Cmd::StopSound).unwrap();
+                }
                 self.screen = match ::core::mem::replace(&mut self.screen, Clock) {
                     Clock => Clock,
                     Menu(mut state) => state.cancel(),
                     SetClock(mut state) => state.cancel(),
                     ManageAlarms(_) => Menu(state::MenuElt::ManageAlarms),
+                    SetNap(_) => Menu(state::MenuElt::Nap),
+                    SetVacation(_) => Menu(state::MenuElt::Vacation),
+                    Exceptions(_) => Menu(state::MenuElt::Exceptions),
+                    SetException(_, 0) => Exceptions(manager.exceptions.len()),
+                    SetException(e, i) => SetException(e, i - 1),
+                    Profiles(_) => Menu(state::MenuElt::Profiles),
+                    RenameProfile(p, _, 0) => Profiles(NB_PROFILES + p),
+                    RenameProfile(p, name, i) => RenameProfile(p, name, i - 1),
+                    History(_) => Menu(state::MenuElt::History),
+                    Sleep(_) => Menu(state::MenuElt::Sleep),
+                    SetSleepTarget(_) => Sleep(1),
+                    Chime(_) => Menu(state::MenuElt::Chime),
+                    SetQuietHour(_, i) => Chime(i),
+                    SetLocation(_, 0) => Menu(state::MenuElt::Location),
+                    SetLocation(location, i) => SetLocation(location, i - 1),
                     ManageAlarm(mut state) => state.cancel(),
+                    Ringing(_) => {
+                        let challenge = manager.ring_policy().challenge;
+                        let now = self.now.to_epoch().unwrap_or(0);
+                        match challenge::Challenge::new(challenge, now, now ^ previous_input) {
+                            Some(c) => Challenge(c),
+                            None => self.solved(&mut cmds),
+                        }
+                    }
+                    Challenge(c) => self.press(c, challenge::Button::Cancel, &mut cmds),
                 };
                 if let Clock = self.screen {
                     cmds.push(Cmd::FullUpdate).unwrap();
@@ -134,45 +322,178 @@ impl Model {
                 Clock => {}
                 Menu(state) => state.next(),
                 SetClock(state) => state.next(),
-                ManageAlarms(i) => *i = (*i + 1) % self.alarm_manager.alarms.len(),
+                ManageAlarms(i) => *i = (*i + 1) % manager.alarms.len(),
+                SetNap(minutes) => *minutes = state::next_nap(*minutes),
+                SetVacation(until) => *until = state::next_vacation(*until, self.now.date()),
+                Exceptions(i) => *i = (*i + 1) % (manager.exceptions.len() + 1),
+                SetException(e, i) => {
+                    state::edit_exception(e, *i, 1, self.now.date(), manager.alarms.len())
+                }
+                Profiles(i) => *i = (*i + 1) % state::NB_PROFILE_ITEMS,
+                RenameProfile(_, name, i) => name.next_char(*i),
+                History(i) => {
+                    let len = history.len();
+                    *i = (*i + 1) % max(len, 1);
+                }
+                Sleep(i) => *i = (*i + 1) % state::NB_SLEEP_ITEMS,
+                SetSleepTarget(config) => state::next_sleep_target(config),
+                Chime(i) => *i = (*i + 1) % state::NB_CHIME_ITEMS,
+                SetQuietHour(config, i) => {
+                    let hour = state::quiet_hour(config, *i);
+                    *hour = (*hour + 1) % 24;
+                }
+                SetLocation(location, i) => state::edit_location(location, *i, 1),
                 ManageAlarm(state) => state.next(),
+                Ringing(_) => {}
+                Challenge(c) => {
+                    let c = c.clone();
+                    self.screen = self.press(c, challenge::Button::Plus, &mut cmds);
+                }
             },
             Msg::ButtonMinus => match &mut self.screen {
                 Clock => {}
                 Menu(state) => state.prev(),
                 SetClock(state) => state.prev(),
                 ManageAlarms(i) => {
-                    let len = self.alarm_manager.alarms.len();
+                    let len = manager.alarms.len();
                     *i = (*i + len - 1) % len;
                 }
                 ManageAlarm(state) => state.prev(),
+                SetNap(minutes) => *minutes = state::prev_nap(*minutes),
+                SetVacation(until) => *until = state::prev_vacation(*until, self.now.date()),
+                Exceptions(i) => {
+                    let len = manager.exceptions.len() + 1;
+                    *i = (*i + len - 1) % len;
+                }
+                SetException(e, i) => {
+                    state::edit_exception(e, *i, -1, self.now.date(), manager.alarms.len())
+                }
+                Profiles(i) => {
+                    *i = (*i + state::NB_PROFILE_ITEMS - 1) % state::NB_PROFILE_ITEMS;
+                }
+                RenameProfile(_, name, i) => name.prev_char(*i),
+                History(i) => {
+                    let len = max(history.len(), 1);
+                    *i = (*i + len - 1) % len;
+                }
+                Sleep(i) => *i = (*i + state::NB_SLEEP_ITEMS - 1) % state::NB_SLEEP_ITEMS,
+                SetSleepTarget(config) => state::prev_sleep_target(config),
+                Chime(i) => *i = (*i + state::NB_CHIME_ITEMS - 1) % state::NB_CHIME_ITEMS,
+                SetQuietHour(config, i) => {
+                    let hour = state::quiet_hour(config, *i);
+                    *hour = (*hour + 23) % 24;
+                }
+                SetLocation(location, i) => state::edit_location(location, *i, -1),
+                Ringing(_) => {}
+                Challenge(c) => {
+                    let c = c.clone();
+                    self.screen = self.press(c, challenge::Button::Minus, &mut cmds);
+                }
             },
         }
         cmds
     }
-    pub fn view(&self) -> Display2in9 {
+    pub fn view(&self, manager: &AlarmManager, history: &History) -> Display2in9 {
         let mut display = Display2in9::default();
         display.set_rotation(DisplayRotation::Rotate270);
 
-        self.render_header(&mut display);
+        self.render_header(manager, &mut display);
 
         use self::state::Screen::*;
         match &self.screen {
             Clock => self.render_clock(&mut display),
             Menu(elt) => self.render_menu(*elt, &mut display),
             SetClock(datetime) => self.render_set_clock(datetime, &mut display),
-            ManageAlarms(i) => self.render_manage_alarms(*i, &mut display),
+            ManageAlarms(i) => self.render_manage_alarms(manager, *i, &mut display),
             ManageAlarm(state) => state.render(&mut display),
+            Ringing(rings) => self.render_ringing(manager, rings, &mut display),
+            Challenge(c) => c.render(&mut display),
+            SetNap(minutes) => self.render_set_nap(*minutes, &mut display),
+            SetVacation(until) => {
+                let mut s: String<U40> = String::new();
+                match until {
+                    None => s.push_str("Alarms: on").unwrap(),
+                    Some(d) => write!(s, "Paused until {} {}", d, d.day_of_week()).unwrap(),
+                }
+                menu::render("Vacation:", &[&s], 0, &mut display);
+            }
+            Exceptions(i) => self.render_exceptions(manager, *i, &mut display),
+            SetException(e, i) => {
+                let mut first: String<U40> = String::new();
+                write!(first, "First day: {} {}", e.first, e.first.day_of_week()).unwrap();
+                let mut last: String<U40> = String::new();
+                write!(last, "Last day: {} {}", e.last, e.last.day_of_week()).unwrap();
+                let mut alarms: String<U40> = String::new();
+                write_truncated(
+                    &mut alarms,
+                    format_args!("Alarms: {}", ExceptionAlarms(e, manager)),
+                );
+                menu::render(
+                    "Exception:",
+                    &[&first, &last, &alarms],
+                    *i as i32,
+                    &mut display,
+                );
+            }
+            Profiles(i) => self.render_profiles(manager, *i, &mut display),
+            RenameProfile(_, name, i) => {
+                let mut s: String<U40> = String::new();
+                s.push_str("Name: ").unwrap();
+                state::write_edited_label(&mut s, name, *i);
+                menu::render("Profiles:", &[&s, "OK on a space to end"], 0, &mut display);
+            }
+            History(i) => self.render_history(manager, history, *i, &mut display),
+            Sleep(i) => self.render_sleep(manager, *i, &mut display),
+            Chime(i) => self.render_chime(manager, *i, &mut display),
+            SetQuietHour(config, i) => {
+                let mut s: String<U40> = String::new();
+                write_quiet_hour(&mut s, config, *i).unwrap();
+                menu::render("Chimes:", &[&s], 0, &mut display);
+            }
+            SetLocation(location, i) => self.render_location(location, *i, &mut display),
+            SetSleepTarget(config) => {
+                let mut s: String<U40> = String::new();
+                write!(
+                    s,
+                    "Sleep target: {}",
+                    Remaining(u32::from(config.target) * 60)
+                )
+                .unwrap();
+                menu::render("Bedtime:", &[&s], 0, &mut display);
+            }
         }
 
         display
     }
+    /// Presses `button` on the challenge `c`, returning the new screen.
+    fn press(
+        &mut self,
+        mut c: challenge::Challenge,
+        button: challenge::Button,
+        cmds: &mut Vec<Cmd, U4>,
+    ) -> state::Screen {
+        if c.press(button, self.now.to_epoch().unwrap_or(0)) {
+            self.solved(cmds)
+        } else {
+            state::Screen::Challenge(c)
+        }
+    }
+    /// Dismisses the ringing alarm, returning the new screen.
+    fn solved(&mut self, cmds: &mut Vec<Cmd, U4>) -> state::Screen {
+        cmds.push(Cmd::Dismiss).unwrap();
+        self.ring_ended()
+    }
+    /// The screen interrupted by the ring, to display again when it
+    /// ends.
+    fn ring_ended(&mut self) -> state::Screen {
+        self.interrupted.take().unwrap_or(state::Screen::Clock)
+    }
     fn update_last_input(&mut self) {
         if let Some(epoch) = self.now.to_epoch() {
             self.last_input = epoch;
         }
     }
-    fn render_header(&self, display: &mut Display2in9) {
+    fn render_header(&self, manager: &AlarmManager, display: &mut Display2in9) {
         let mut header = header::Header::new(display);
         let mut s: String<U128> = String::new();
 
@@ -184,21 +505,78 @@ impl Model {
         .unwrap();
         header.top_left(&s);
 
-        match self.alarm_manager.next_ring(&self.now) {
-            None => header.bottom_left("No alarm"),
-            Some((dow, h, m)) => {
-                s.clear();
-                write!(s, "Alarm: {} {}:{:02}", dow, h, m).unwrap();
-                header.bottom_left(&s);
-            }
+        let mut right: String<U32> = String::new();
+        if self.nb_fail_environment != 0 {
+            write!(right, "({}s) ", self.nb_fail_environment).unwrap();
         }
+        write!(right, "{}hPa", Centi(self.env.pressure as i32)).unwrap();
+        header.bottom_right(&right);
+        // characters available at the left of `right`
+        let room = header::NB_CHARS - right.chars().count() - 1;
 
-        s.clear();
-        if self.nb_fail_environment != 0 {
-            write!(s, "({}s) ", self.nb_fail_environment).unwrap();
+        if let Some(ringing) = manager.ringing() {
+            s.clear();
+            write!(s, "Ringing: {}:{:02}", ringing.hour, ringing.min).unwrap();
+            let label = manager.label();
+            if !label.is_empty() {
+                write!(s, " {}", label).unwrap();
+            }
+            header.bottom_left(&s);
+        } else if let Some(e) = self.save_error {
+            s.clear();
+            write!(s, "Settings not saved: {}", e).unwrap();
+            header.bottom_left(&s);
+        } else if let Some(snooze) = manager.snoozed_until() {
+            s.clear();
+            write!(
+                s,
+                "Snooze: {}:{:02} ({} left)",
+                snooze.hour,
+                snooze.min,
+                manager.remaining_snoozes()
+            )
+            .unwrap();
+            header.bottom_left(&s);
+        } else if let Some(missed) = manager.missed() {
+            s.clear();
+            write!(s, "Missed: {}:{:02}", missed.hour, missed.min).unwrap();
+            header.bottom_left(&s);
+        } else if manager.is_suspended(self.now.date()) {
+            s.clear();
+            if let Some(until) = manager.suspended_until {
+                write!(s, "Alarms paused until {:02}-{:02}", until.month, until.day).unwrap();
+            }
+            header.bottom_left(&s);
+        } else {
+            match manager.next_ring(&self.now) {
+                None => header.bottom_left("No alarm"),
+                Some(next) => {
+                    s.clear();
+                    let date = next.datetime.date();
+                    let days = match (self.now.date().to_days(), date.to_days()) {
+                        (Some(today), Some(day)) => Some(day.saturating_sub(today)),
+                        _ => None,
+                    };
+                    s.push_str("Alarm: ").unwrap();
+                    match days {
+                        Some(0) => s.push_str("today").unwrap(),
+                        Some(1) => s.push_str("tomorrow").unwrap(),
+                        Some(2..=6) => write!(s, "{}", date.day_of_week()).unwrap(),
+                        _ => write!(s, "{}", date).unwrap(),
+                    }
+                    write!(s, " {:02}:{:02}", next.datetime.hour, next.datetime.min).unwrap();
+                    if let Some(now) = self.now.to_epoch() {
+                        let remaining = Remaining(next.epoch.saturating_sub(now));
+                        push_if_fits(&mut s, room, format_args!(" (in {})", remaining));
+                    }
+                    let label = manager.label_of(&next);
+                    if !label.is_empty() {
+                        push_if_fits(&mut s, room, format_args!(" {}", label));
+                    }
+                    header.bottom_left(&s);
+                }
+            }
         }
-        write!(s, "{}hPa", Centi(self.env.pressure as i32)).unwrap();
-        header.bottom_right(&s);
 
         s.clear();
         if self.env.humidity != 0 {
@@ -248,22 +626,334 @@ impl Model {
         .unwrap();
         menu::render(&title, &[dt.as_edit_str()], 0, display);
     }
-    fn render_manage_alarms(&self, i: usize, display: &mut Display2in9) {
-        let v: Vec<_, U5> = self
-            .alarm_manager
+    fn render_set_nap(&self, minutes: u8, display: &mut Display2in9) {
+        let mut s: String<U40> = String::new();
+        if minutes == 0 {
+            s.push_str("Nap: off").unwrap();
+        } else {
+            write!(s, "Nap: {} min", minutes).unwrap();
+            if let Some(nap) = alarm::nap_end(&self.now, minutes) {
+                write!(s, ", until {:02}:{:02}", nap.hour, nap.min).unwrap();
+            }
+        }
+        menu::render("Nap:", &[&s], 0, display);
+    }
+    fn render_ringing(&self, manager: &AlarmManager, rings: &Rings, display: &mut Display2in9) {
+        let mut alarm = String::<U40>::new();
+        let mut others = rings.alarms().count() + rings.nap as usize;
+        match rings.alarms().next() {
+            None => alarm.push_str("Nap").unwrap(),
+            Some(i) => {
+                write!(alarm, "#{}", i + 1).unwrap();
+                let label = manager.alarms[i].label;
+                if !label.is_empty() {
+                    write!(alarm, " {}", label).unwrap();
+                }
+            }
+        }
+        others -= 1;
+        if others > 0 {
+            write!(alarm, " +{}", others).unwrap();
+        }
+
+        let mut elapsed = String::<U40>::new();
+        let start = manager.ringing().and_then(|dt| dt.to_epoch());
+        if let (Some(start), Some(now)) = (start, self.now.to_epoch()) {
+            let sec = now.saturating_sub(start);
+            write!(elapsed, "Ringing for {}:{:02}", sec / 60, sec % 60).unwrap();
+        }
+
+        let cancel = match manager.ring_policy().challenge {
+            alarm::Challenge::Press => "Cancel: dismiss",
+            _ => "Cancel: challenge",
+        };
+        let mut ok = String::<U40>::new();
+        match manager.remaining_snoozes() {
+            0 => ok.push_str("OK: dismiss").unwrap(),
+            n => write!(ok, "OK: snooze ({} left)", n).unwrap(),
+        }
+        menu::render("Ringing:", &[&alarm, &elapsed, cancel, &ok], 0, display);
+    }
+    fn render_exceptions(&self, manager: &AlarmManager, i: usize, display: &mut Display2in9) {
+        let mut v: Vec<String<U40>, U9> = manager
+            .exceptions
+            .iter()
+            .map(|e| {
+                let mut s = String::new();
+                write_truncated(
+                    &mut s,
+                    format_args!(
+                        "Remove {:02}-{:02} - {:02}-{:02} {}",
+                        e.first.month,
+                        e.first.day,
+                        e.last.month,
+                        e.last.day,
+                        ExceptionAlarms(e, manager)
+                    ),
+                );
+                s
+            })
+            .collect();
+        let add = if manager.exceptions.len() < manager.exceptions.capacity() {
+            "Add an exception"
+        } else {
+            "No room for another exception"
+        };
+        v.push(String::from(add)).unwrap();
+        let v: Vec<&str, U9> = v.iter().map(|s| s.as_str()).collect();
+        menu::render("Exceptions:", &v, i as i32, display);
+    }
+    fn render_profiles(&self, manager: &AlarmManager, i: usize, display: &mut Display2in9) {
+        let v: Vec<String<U40>, U8> = (0..state::NB_PROFILE_ITEMS)
+            .map(|item| {
+                let p = item % NB_PROFILES;
+                let name = state::ProfileName(p, &manager.profiles[p]);
+                let mut s = String::new();
+                if item >= NB_PROFILES {
+                    write!(s, "Rename {}", name).unwrap();
+                } else if p == usize::from(manager.active_profile) {
+                    write!(s, "Use {} (active)", name).unwrap();
+                } else {
+                    write!(s, "Use {}", name).unwrap();
+                }
+                s
+            })
+            .collect();
+        let v: Vec<&str, U8> = v.iter().map(|s| s.as_str()).collect();
+        menu::render("Profiles:", &v, i as i32, display);
+    }
+    fn render_chime(&self, manager: &AlarmManager, i: usize, display: &mut Display2in9) {
+        let config = &manager.chime_config;
+        let mut kind: String<U40> = String::new();
+        write!(kind, "Chime: {}", config.kind).unwrap();
+        let quarters = if config.quarters {
+            "Quarters: on"
+        } else {
+            "Quarters: off"
+        };
+        let mut from: String<U40> = String::new();
+        write_quiet_hour(&mut from, config, state::QUIET_FROM_ITEM).unwrap();
+        let mut until: String<U40> = String::new();
+        write_quiet_hour(&mut until, config, state::QUIET_FROM_ITEM + 1).unwrap();
+        menu::render(
+            "Chimes:",
+            &[&kind, quarters, &from, &until],
+            i as i32,
+            display,
+        );
+    }
+    fn render_location(&self, location: &datetime::Location, i: usize, display: &mut Display2in9) {
+        let mut title: String<U40> = String::new();
+        write!(title, "Location, by {}:", state::location_step(i)).unwrap();
+        let mut latitude: String<U40> = String::new();
+        write!(latitude, "Latitude: {}", location.latitude()).unwrap();
+        let mut longitude: String<U40> = String::new();
+        write!(longitude, "Longitude: {}", location.longitude()).unwrap();
+        let offset = location.utc_offset;
+        let sign = if offset < 0 { '-' } else { '+' };
+        let (h, m) = (offset.abs() / 60, offset.abs() % 60);
+        let mut utc_offset: String<U40> = String::new();
+        write!(utc_offset, "UTC offset: {}{:02}:{:02}", sign, h, m).unwrap();
+        let mut today: String<U40> = String::new();
+        let today_event = |event| datetime::sun_event(self.now.date(), *location, event);
+        match (
+            today_event(datetime::SunEvent::Sunrise),
+            today_event(datetime::SunEvent::Sunset),
+        ) {
+            (Some((h, m)), Some((h2, m2))) => {
+                write!(today, "Today: {:02}:{:02} - {:02}:{:02}", h, m, h2, m2).unwrap()
+            }
+            _ => today.push_str("Today: no sunrise").unwrap(),
+        }
+        menu::render(
+            &title,
+            &[&latitude, &longitude, &utc_offset, &today],
+            (i / 2) as i32,
+            display,
+        );
+    }
+    fn render_sleep(&self, manager: &AlarmManager, i: usize, display: &mut Display2in9) {
+        let config = manager.sleep_config;
+        let mut target: String<U40> = String::new();
+        let target_sec = u32::from(config.target) * 60;
+        write!(target, "Sleep target: {}", Remaining(target_sec)).unwrap();
+        let mut bedtime: String<U40> = String::new();
+        let mut cycles: String<U40> = String::new();
+        match manager.next_ring(&self.now) {
+            None => {
+                bedtime.push_str("Bedtime: no alarm").unwrap();
+                cycles.push_str("Cycles: no alarm").unwrap();
+            }
+            Some(next) => {
+                let dt = datetime::DateTime::new(config.bedtime(next.epoch));
+                write!(bedtime, "Bedtime: {:02}:{:02}", dt.hour, dt.min).unwrap();
+                write!(
+                    bedtime,
+                    " for {:02}:{:02}",
+                    next.datetime.hour, next.datetime.min
+                )
+                .unwrap();
+                cycles.push_str("Cycles:").unwrap();
+                for &nb in &sleep::SUGGESTED_CYCLES {
+                    let dt = datetime::DateTime::new(sleep::cycle_bedtime(next.epoch, nb));
+                    write!(cycles, " {:02}:{:02}", dt.hour, dt.min).unwrap();
+                }
+            }
+        }
+        let items = [
+            if config.reminder {
+                "Reminder: on"
+            } else {
+                "Reminder: off"
+            },
+            &target,
+            &bedtime,
+            &cycles,
+        ];
+        menu::render("Bedtime:", &items, i as i32, display);
+    }
+    fn render_history(
+        &self,
+        manager: &AlarmManager,
+        history: &History,
+        i: usize,
+        display: &mut Display2in9,
+    ) {
+        if history.is_empty() {
+            menu::render("History:", &["No ring yet"], 0, display);
+            return;
+        }
+        let v: Vec<_, NbEvents> = history
+            .iter()
+            .map(|event| {
+                let mut s = String::<U40>::new();
+                write_truncated(&mut s, format_args!("{}", EventText(event, manager)));
+                s
+            })
+            .collect();
+        let v: Vec<&str, NbEvents> = v.iter().map(|s| s.as_str()).collect();
+        menu::render("History:", &v, i as i32, display);
+    }
+    fn render_manage_alarms(&self, manager: &AlarmManager, i: usize, display: &mut Display2in9) {
+        let v: Vec<_, NbAlarms> = manager
             .alarms
             .iter()
             .map(|a| {
                 let mut s = String::<U40>::new();
-                write!(s, "{}", a).unwrap();
+                write_truncated(&mut s, format_args!("{}", a));
                 s
             })
             .collect();
-        let v: Vec<&str, U5> = v.iter().map(|s| s.as_str()).collect();
+        let v: Vec<&str, NbAlarms> = v.iter().map(|s| s.as_str()).collect();
         menu::render("Select alarm:", &v, i as i32, display);
     }
 }
 
+/// Writes the quiet hour of the item `i` of the chimes menu.
+fn write_quiet_hour(s: &mut String<U40>, config: &ChimeConfig, i: usize) -> fmt::Result {
+    if i == state::QUIET_FROM_ITEM {
+        write!(s, "Quiet from {:02}:00", config.quiet_from)
+    } else {
+        write!(s, "Quiet until {:02}:00", config.quiet_until)
+    }
+}
+
+/// Appends `args` to `s` only if `s` has then at most `max`
+/// characters.
+fn push_if_fits<N: heapless::ArrayLength<u8>>(s: &mut String<N>, max: usize, args: fmt::Arguments) {
+    let len = s.len();
+    if s.write_fmt(args).is_err() || s.chars().count() > max {
+        s.truncate(len);
+    }
+}
+
+/// Writes `args` to `s`, dropping what does not fit: the text is
+/// wider than the screen anyway.
+fn write_truncated<N: heapless::ArrayLength<u8>>(s: &mut String<N>, args: fmt::Arguments) {
+    struct Truncating<'a, N: heapless::ArrayLength<u8>>(&'a mut String<N>);
+    impl<N: heapless::ArrayLength<u8>> Write for Truncating<'_, N> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            for c in s.chars() {
+                if self.0.push(c).is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        }
+    }
+    // only a failing `Display` implementation can return an error
+    let _ = Truncating(s).write_fmt(args);
+}
+
+/// A ring of the history, as "10-15 07:25 #1 Work dismissed 07:31".
+struct EventText<'a>(&'a history::Event, &'a AlarmManager);
+impl fmt::Display for EventText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (event, manager) = (self.0, self.1);
+        let ring = datetime::DateTime::new(event.ring);
+        write!(
+            f,
+            "{:02}-{:02} {:02}:{:02} ",
+            ring.month, ring.day, ring.hour, ring.min
+        )?;
+        match event.alarm {
+            None => f.write_str("Nap")?,
+            Some(i) => {
+                write!(f, "#{}", i + 1)?;
+                let label = manager.alarms[usize::from(i)].label;
+                if !label.is_empty() {
+                    write!(f, " {}", label)?;
+                }
+            }
+        }
+        match event.end {
+            None => f.write_str(" ringing"),
+            Some((_, history::Outcome::Missed)) => f.write_str(" missed"),
+            Some((end, outcome)) => {
+                let end = datetime::DateTime::new(end);
+                write!(f, " {} {:02}:{:02}", outcome, end.hour, end.min)
+            }
+        }
+    }
+}
+
+/// The alarms of an exception, as "all" or "#3 Work".
+struct ExceptionAlarms<'a>(&'a Exception, &'a AlarmManager);
+impl fmt::Display for ExceptionAlarms<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (e, manager) = (self.0, self.1);
+        if e.alarms == Exception::ALL_ALARMS {
+            return f.write_str("all");
+        }
+        let mut sep = "";
+        for (i, alarm) in manager.alarms.iter().enumerate() {
+            if e.applies_to(i) {
+                write!(f, "{}#{}", sep, i + 1)?;
+                sep = " ";
+                if !alarm.label.is_empty() {
+                    write!(f, " {}", alarm.label)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A duration in seconds, displayed in minutes.
+struct Remaining(u32);
+impl fmt::Display for Remaining {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let min = self.0 / 60;
+        if min < 60 {
+            write!(f, "{} min", min)
+        } else if min < 24 * 60 {
+            write!(f, "{}h{:02}", min / 60, min % 60)
+        } else {
+            write!(f, "{} days", min / (24 * 60))
+        }
+    }
+}
+
 struct Centi(i32);
 impl fmt::Display for Centi {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -297,9 +987,143 @@ impl Default for Environment {
 
 #[cfg(test)]
 mod test {
-    use super::Centi;
+    use super::state::{MenuElt, Screen};
+    use super::{write_truncated, Centi, Cmd, Model, Msg, Remaining};
+    use crate::alarm::{self, Alarm, AlarmManager};
+    use crate::datetime::{Date, DateTime};
+    use crate::history::History;
+    use crate::store;
+    use heapless::{consts::*, String};
     use std::string::ToString;
 
+    /// A model with its alarm manager ringing at `epoch`.
+    fn ringing_model(challenge: alarm::Challenge, epoch: u32) -> (Model, AlarmManager) {
+        let h = History::default();
+        let mut manager = AlarmManager::default();
+        manager.alarms[0] = Alarm::parse("07:25 daily on").unwrap();
+        manager.alarms[0].ring_policy.challenge = challenge;
+        let rings = manager.must_ring(&DateTime::new(epoch)).unwrap();
+        let mut model = Model::init();
+        model.update(Msg::DateTime(DateTime::new(epoch)), &manager, &h);
+        model.update(Msg::AlarmManagerUpdated, &manager, &h);
+        model.update(Msg::AlarmRinging(rings), &manager, &h);
+        assert!(matches!(model.screen, Screen::Ringing(r) if r.contains(0)));
+        (model, manager)
+    }
+
+    #[test]
+    fn ringing() {
+        let h = History::default();
+        let epoch = 1_539_588_300;
+        let (mut model, m) = ringing_model(alarm::Challenge::Press, epoch);
+        model.update(Msg::ButtonPlus, &m, &h);
+        model.update(Msg::DateTime(DateTime::new(epoch + 11 * 60)), &m, &h);
+        assert!(matches!(model.screen, Screen::Ringing(_)));
+        let cmds = model.update(Msg::ButtonOk, &m, &h);
+        assert!(matches!(cmds[0], Cmd::Snooze));
+        assert_eq!(model.screen, Screen::Clock);
+
+        let (mut model, m) = ringing_model(alarm::Challenge::Press, epoch);
+        let cmds = model.update(Msg::ButtonCancel, &m, &h);
+        assert!(matches!(cmds[0], Cmd::Dismiss));
+        assert_eq!(model.screen, Screen::Clock);
+        let cmds = model.update(Msg::ButtonCancel, &m, &h);
+        assert!(matches!(cmds[0], Cmd::StopSound));
+
+        let (mut model, m) = ringing_model(alarm::Challenge::Hold, epoch);
+        let cmds = model.update(Msg::ButtonCancel, &m, &h);
+        assert!(cmds.is_empty());
+        assert!(matches!(model.screen, Screen::Challenge(_)));
+        let cmds = model.update(Msg::DateTime(DateTime::new(epoch + 4)), &m, &h);
+        assert!(matches!(cmds[0], Cmd::Dismiss));
+        assert_eq!(model.screen, Screen::Clock);
+
+        // the ring ends without any input
+        let (mut model, mut m) = ringing_model(alarm::Challenge::Press, epoch);
+        assert!(m.check_missed(&DateTime::new(epoch + 3600)));
+        model.update(Msg::AlarmManagerUpdated, &m, &h);
+        assert_eq!(model.screen, Screen::Clock);
+    }
+
+    #[test]
+    fn interrupted() {
+        let h = History::default();
+        let epoch = 1_539_588_300;
+        let mut manager = AlarmManager::default();
+        manager.alarms[0] = Alarm::parse("07:25 daily on").unwrap();
+        let mut model = Model::init();
+        model.update(Msg::DateTime(DateTime::new(epoch - 60)), &manager, &h);
+        model.screen = Screen::Menu(MenuElt::ManageAlarms);
+        model.update(Msg::ButtonOk, &manager, &h);
+        model.update(Msg::ButtonOk, &manager, &h);
+        model.update(Msg::ButtonPlus, &manager, &h);
+        let edited = model.screen.clone();
+        assert!(matches!(edited, Screen::ManageAlarm(_)));
+
+        let rings = manager.must_ring(&DateTime::new(epoch)).unwrap();
+        model.update(Msg::AlarmManagerUpdated, &manager, &h);
+        model.update(Msg::AlarmRinging(rings), &manager, &h);
+        let cmds = model.update(Msg::ButtonCancel, &manager, &h);
+        assert!(matches!(cmds[0], Cmd::Dismiss));
+        assert_eq!(model.screen, edited);
+    }
+
+    #[test]
+    fn exceptions() {
+        let h = History::default();
+        let mut m = AlarmManager::default();
+        let mut model = Model::init();
+        model.update(Msg::DateTime(DateTime::new(1_539_588_300)), &m, &h);
+        model.screen = Screen::Menu(MenuElt::Exceptions);
+        model.update(Msg::ButtonOk, &m, &h);
+        model.update(Msg::ButtonOk, &m, &h);
+        model.update(Msg::ButtonPlus, &m, &h);
+        model.update(Msg::ButtonOk, &m, &h);
+        model.update(Msg::ButtonPlus, &m, &h);
+        model.update(Msg::ButtonOk, &m, &h);
+        model.update(Msg::ButtonMinus, &m, &h);
+        let cmds = model.update(Msg::ButtonOk, &m, &h);
+        let e = match cmds[0] {
+            Cmd::AddException(e) => e,
+            _ => panic!("no exception added"),
+        };
+        let tomorrow = Date {
+            year: 2018,
+            month: 10,
+            day: 16,
+        };
+        assert_eq!((e.first, e.last), (tomorrow, tomorrow.next()));
+        assert_eq!(e.alarms, 1 << (m.alarms.len() - 1));
+
+        m.exceptions.push(e).unwrap();
+        model.update(Msg::AlarmManagerUpdated, &m, &h);
+        assert_eq!(model.screen, Screen::Exceptions(0));
+        let cmds = model.update(Msg::ButtonOk, &m, &h);
+        assert!(matches!(cmds[0], Cmd::RemoveException(0)));
+    }
+
+    #[test]
+    fn save_error() {
+        let (h, m) = (History::default(), AlarmManager::default());
+        let mut model = Model::init();
+        model.update(Msg::Saved(Err(store::Error::Full)), &m, &h);
+        assert_eq!(model.save_error, Some(store::Error::Full));
+        // kept until a save succeeds
+        model.update(Msg::AlarmManagerUpdated, &m, &h);
+        assert_eq!(model.save_error, Some(store::Error::Full));
+        model.update(Msg::Saved(Ok(())), &m, &h);
+        assert_eq!(model.save_error, None);
+    }
+
+    #[test]
+    fn truncated() {
+        let mut s = String::<U8>::new();
+        write_truncated(&mut s, format_args!("Edit: {}", Alarm::default()));
+        assert_eq!(s, "Edit: Of");
+        write_truncated(&mut s, format_args!("{}", 0));
+        assert_eq!(s, "Edit: Of");
+    }
+
     #[test]
     fn centi() {
         assert_eq!(Centi(core::i32::MAX).to_string(), "21474836.47");
@@ -321,4 +1145,12 @@ mod test {
 
         assert_eq!(Centi(core::i32::MIN).to_string(), "-21474836.48");
     }
+
+    #[test]
+    fn remaining() {
+        assert_eq!(Remaining(59).to_string(), "0 min");
+        assert_eq!(Remaining(25 * 60).to_string(), "25 min");
+        assert_eq!(Remaining(8 * 3600 + 12 * 60 + 30).to_string(), "8h12");
+        assert_eq!(Remaining(3 * 86400 + 3600).to_string(), "3 days");
+    }
 }
@@ -1,6 +1,13 @@
-use super::{menu, Cmd};
-use crate::alarm::{Alarm, AlarmManager, Mode};
-use crate::datetime;
+use super::{challenge, menu, write_truncated, Cmd};
+use crate::alarm::{
+    Alarm, AlarmManager, Conflict, Exception, Label, Mode, Rings, Rotation, Slot, Solar,
+    CONFLICT_MIN, LABEL_LEN, MAX_NAP, MAX_PRE_ALARM, MAX_SLOT_DAYS, MAX_SOLAR_OFFSET, NB_PROFILES,
+    NB_SLOTS,
+};
+use crate::chime::ChimeConfig;
+use crate::datetime::{self, Country, Date, Location, SunEvent};
+use crate::sleep::{self, SleepConfig};
+use core::cmp::{max, min};
 use core::fmt::Write;
 use epd_waveshare::epd2in9::Display2in9;
 use heapless::{consts::*, String, Vec};
@@ -22,6 +29,33 @@ pub enum Screen {
     SetClock(EditDateTime),
     ManageAlarms(usize),
     ManageAlarm(ManageAlarm),
+    /// The alarms beginning to ring.
+    Ringing(Rings),
+    Challenge(challenge::Challenge),
+    /// Duration of the nap, unit: min, 0 to cancel the nap.
+    SetNap(u8),
+    /// The end of the suspension of the alarms, `None` to resume them.
+    SetVacation(Option<Date>),
+    /// The exceptions menu, with the selected item: removing each
+    /// exception, then adding one.
+    Exceptions(usize),
+    /// The added exception, with the edited field.
+    SetException(Exception, usize),
+    /// The profiles menu, with the selected item.
+    Profiles(usize),
+    /// The renamed profile, its new name and the edited character.
+    RenameProfile(usize, Label, usize),
+    /// The ring history, with the selected event.
+    History(usize),
+    /// The bedtime menu, with the selected item.
+    Sleep(usize),
+    SetSleepTarget(SleepConfig),
+    /// The chimes menu, with the selected item.
+    Chime(usize),
+    /// The edited quiet hours, with the item of the edited hour.
+    SetQuietHour(ChimeConfig, usize),
+    /// The edited location, with the edited field.
+    SetLocation(Location, usize),
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -29,6 +63,14 @@ pub enum MenuElt {
     Clock,
     SetClock,
     ManageAlarms,
+    Profiles,
+    Nap,
+    Vacation,
+    Exceptions,
+    History,
+    Sleep,
+    Chime,
+    Location,
 }
 impl MenuElt {
     pub fn next(&mut self) {
@@ -36,25 +78,207 @@ impl MenuElt {
         *self = match *self {
             Clock => SetClock,
             SetClock => ManageAlarms,
-            ManageAlarms => Clock,
+            ManageAlarms => Profiles,
+            Profiles => Nap,
+            Nap => Vacation,
+            Vacation => Exceptions,
+            Exceptions => History,
+            History => Sleep,
+            Sleep => Chime,
+            Chime => Location,
+            Location => Clock,
         }
     }
     pub fn prev(&mut self) {
         use self::MenuElt::*;
         *self = match *self {
-            Clock => ManageAlarms,
+            Clock => Location,
             SetClock => Clock,
             ManageAlarms => SetClock,
+            Profiles => ManageAlarms,
+            Nap => Profiles,
+            Vacation => Nap,
+            Exceptions => Vacation,
+            History => Exceptions,
+            Sleep => History,
+            Chime => Sleep,
+            Location => Chime,
         }
     }
     pub fn cancel(&mut self) -> Screen {
         Screen::Clock
     }
     pub fn items(self) -> &'static [&'static str] {
-        &["Main screen", "Set clock", "Manage alarms"]
+        &[
+            "Main screen",
+            "Set clock",
+            "Manage alarms",
+            "Profiles",
+            "Nap",
+            "Vacation",
+            "Exceptions",
+            "History",
+            "Bedtime",
+            "Chimes",
+            "Location",
+        ]
     }
 }
 
+/// Number of fields of the location screen: the latitude and the
+/// longitude by degrees then by hundredths, and the UTC offset.
+pub const NB_LOCATION_FIELDS: usize = 5;
+const LOCATION_STEPS: [i16; NB_LOCATION_FIELDS] = [100, 5, 100, 5, 15];
+
+/// Moves the field `i` of the location by one step in the direction
+/// of `sign`.
+pub fn edit_location(location: &mut Location, i: usize, sign: i16) {
+    let (value, max) = match i {
+        0 | 1 => (&mut location.latitude, datetime::MAX_LATITUDE),
+        2 | 3 => (&mut location.longitude, datetime::MAX_LONGITUDE),
+        _ => (&mut location.utc_offset, datetime::MAX_UTC_OFFSET),
+    };
+    *value = (*value + sign * LOCATION_STEPS[i]).max(-max).min(max);
+}
+/// The unit of the steps of the field `i` of the location.
+pub fn location_step(i: usize) -> &'static str {
+    match i {
+        0 | 2 => "1 degree",
+        1 | 3 => "0.05 degree",
+        _ => "15 min",
+    }
+}
+
+/// Default duration of a nap, unit: min.
+pub const DEFAULT_NAP: u8 = 20;
+const NAP_STEP: u8 = 5;
+
+/// Number of items of the bedtime menu.
+pub const NB_SLEEP_ITEMS: usize = 4;
+const SLEEP_TARGET_STEP: u16 = 15;
+
+pub fn next_sleep_target(config: &mut SleepConfig) {
+    config.target = config.target % sleep::MAX_TARGET + SLEEP_TARGET_STEP;
+}
+pub fn prev_sleep_target(config: &mut SleepConfig) {
+    config.target = (config.target + sleep::MAX_TARGET - 2 * SLEEP_TARGET_STEP) % sleep::MAX_TARGET
+        + SLEEP_TARGET_STEP;
+}
+
+/// Number of items of the chimes menu.
+pub const NB_CHIME_ITEMS: usize = 4;
+/// Item of the chimes menu editing the beginning of the quiet hours.
+pub const QUIET_FROM_ITEM: usize = 2;
+
+/// The quiet hour edited by the item `i` of the chimes menu.
+pub fn quiet_hour(config: &mut ChimeConfig, i: usize) -> &mut u8 {
+    if i == QUIET_FROM_ITEM {
+        &mut config.quiet_from
+    } else {
+        &mut config.quiet_until
+    }
+}
+
+/// Number of items of the profiles menu, activating then renaming
+/// each profile.
+pub const NB_PROFILE_ITEMS: usize = 2 * NB_PROFILES;
+
+/// The name of the profile `p`, a default name if empty.
+pub struct ProfileName<'a>(pub usize, pub &'a Label);
+impl core::fmt::Display for ProfileName<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        if self.1.is_empty() {
+            write!(f, "Profile {}", self.0 + 1)
+        } else {
+            write!(f, "{}", self.1)
+        }
+    }
+}
+
+/// OK on the character `i` of the new name of the profile `p`.
+pub fn rename_profile_ok(p: usize, mut name: Label, i: usize, cmds: &mut Vec<Cmd, U4>) -> Screen {
+    // OK on a space ends the name
+    if name.char_at(i) == ' ' {
+        name.truncate(i);
+    } else if i + 1 < LABEL_LEN {
+        return Screen::RenameProfile(p, name, i + 1);
+    }
+    cmds.push(Cmd::RenameProfile(p as u8, name)).unwrap();
+    Screen::Profiles(NB_PROFILES + p)
+}
+
+/// Writes `label` with the character at `pos` being edited.
+pub fn write_edited_label(s: &mut String<U40>, label: &Label, pos: usize) {
+    for i in 0..LABEL_LEN {
+        let c = label.char_at(i);
+        if i == pos {
+            write!(s, "[{}]", c).unwrap();
+        } else {
+            s.push(c).unwrap();
+        }
+    }
+}
+
+/// The next end of the suspension, from today.
+pub fn next_vacation(until: Option<Date>, today: Date) -> Option<Date> {
+    Some(match until {
+        Some(until) => until.next(),
+        None => today,
+    })
+}
+/// The previous end of the suspension, `None` before today.
+pub fn prev_vacation(until: Option<Date>, today: Date) -> Option<Date> {
+    match until {
+        Some(until) if until > today => Some(Date::new(until.to_days()? - 1)),
+        _ => None,
+    }
+}
+
+/// Number of fields of the added exception: its first and last
+/// days, then its alarms.
+pub const NB_EXCEPTION_FIELDS: usize = 3;
+
+/// Moves the field `i` of the exception by one step in the direction
+/// of `sign`, the days staying from today, the alarms cycling from
+/// every alarm to each of the `nb_alarms` alarms.
+pub fn edit_exception(e: &mut Exception, i: usize, sign: i8, today: Date, nb_alarms: usize) {
+    let step = |date: Date, min: Date| {
+        let days = date.to_days().unwrap_or(0);
+        let date = if sign > 0 {
+            Date::new(days + 1)
+        } else {
+            Date::new(days.saturating_sub(1))
+        };
+        max(date, min)
+    };
+    match i {
+        0 => {
+            e.first = step(e.first, today);
+            e.last = max(e.last, e.first);
+        }
+        1 => e.last = step(e.last, e.first),
+        _ => {
+            // 0 for every alarm, i + 1 for the alarm i
+            let cur = if e.alarms == Exception::ALL_ALARMS {
+                0
+            } else {
+                e.alarms.trailing_zeros() as i32 + 1
+            };
+            e.alarms = match (cur + i32::from(sign)).rem_euclid(nb_alarms as i32 + 1) {
+                0 => Exception::ALL_ALARMS,
+                n => 1 << (n - 1),
+            };
+        }
+    }
+}
+
+pub fn next_nap(minutes: u8) -> u8 {
+    (minutes + NAP_STEP) % (MAX_NAP + NAP_STEP)
+}
+pub fn prev_nap(minutes: u8) -> u8 {
+    (minutes + MAX_NAP) % (MAX_NAP + NAP_STEP)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EditDateTime {
     pub datetime: datetime::DateTime,
@@ -143,18 +367,29 @@ impl EditDateTime {
 pub struct ManageAlarm {
     id: usize,
     alarm: Alarm,
+    today: datetime::Date,
     state: ManageAlarmState,
 }
 impl ManageAlarm {
-    pub fn new(manager: &AlarmManager, id: usize) -> Self {
+    pub fn new(manager: &AlarmManager, id: usize, today: datetime::Date) -> Self {
+        let today = if today.year < MIN_ALARM_YEAR || today.year > MAX_ALARM_YEAR {
+            datetime::Date {
+                year: MIN_ALARM_YEAR,
+                month: 1,
+                day: 1,
+            }
+        } else {
+            today
+        };
         Self {
             id,
             alarm: manager.alarms[id].clone(),
+            today,
             state: ManageAlarmState::Main(ManageAlarmMainState::ToggleEnable),
         }
     }
-    pub fn ok(&self, cmds: &mut Vec<Cmd, U4>) -> Screen {
-        self.state.ok(&self, cmds)
+    pub fn ok(&self, manager: &AlarmManager, cmds: &mut Vec<Cmd, U4>) -> Screen {
+        self.state.ok(&self, manager, cmds)
     }
     pub fn next(&mut self) {
         self.state = self.state.next(&mut self.alarm);
@@ -175,18 +410,110 @@ impl ManageAlarm {
         self.state.render(&self.alarm, display);
     }
 }
+/// unit: min
+const MAX_RING_DURATION: u8 = 60;
+const MIN_ALARM_YEAR: u16 = 2000;
+const MAX_ALARM_YEAR: u16 = 2127;
+
+/// Formats a pre-alarm window.
+struct PreAlarm(u8);
+impl core::fmt::Display for PreAlarm {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        if self.0 == 0 {
+            f.write_str("off")
+        } else {
+            write!(f, "{} min", self.0)
+        }
+    }
+}
+
+const SOLAR_OFFSET_STEP: i16 = 5;
+
+fn edit_solar(alarm: &mut Alarm, f: impl FnOnce(&mut Solar)) {
+    if let Some(mut solar) = alarm.solar() {
+        f(&mut solar);
+        alarm.set_solar(Some(solar));
+    }
+}
+
+/// Formats the solar time of an alarm.
+struct SolarTime(Option<Solar>);
+impl core::fmt::Display for SolarTime {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self.0 {
+            None => f.write_str("off"),
+            Some(solar) => write!(f, "{}", solar),
+        }
+    }
+}
+
+fn edit_rotation(alarm: &mut Alarm, f: impl FnOnce(&mut Rotation)) {
+    if let Some(mut rotation) = alarm.rotation() {
+        f(&mut rotation);
+        alarm.set_rotation(Some(rotation));
+    }
+}
+
+/// Moves the date by `days`, keeping it in the alarm years.
+fn add_days(date: datetime::Date, days: i32) -> datetime::Date {
+    let moved = date
+        .to_days()
+        .map(|d| datetime::Date::new((d as i32 + days) as u32));
+    match moved {
+        Some(moved) if moved.year >= MIN_ALARM_YEAR && moved.year <= MAX_ALARM_YEAR => moved,
+        _ => date,
+    }
+}
+
+fn slot(alarm: &Alarm, i: usize) -> Slot {
+    alarm.rotation().map_or(Slot::default(), |r| r.slots[i])
+}
+
+fn edit_date(alarm: &mut Alarm, f: impl FnOnce(&mut datetime::Date)) {
+    if let Some(mut date) = alarm.date() {
+        f(&mut date);
+        date.day = min(date.day, date.nb_days_in_month());
+        alarm.set_date(Some(date));
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ManageAlarmState {
     Main(ManageAlarmMainState),
     SetHour,
     SetMin,
+    SetYear,
+    SetMonth,
+    SetDay,
+    SetRingDuration,
+    SetEscalateAfter,
+    SetEscalation,
+    SetPreAlarm,
+    SetSolarEvent,
+    SetSolarOffset,
+    /// Character picker, editing the character at the given position.
+    SetLabel(usize),
+    /// Rotation menu, with the selected item.
+    ManageRotation(usize),
+    SetRotationStart,
+    SetSlotDays(usize),
+    SetSlotHour(usize),
+    SetSlotMin(usize),
     ManageRepeat(ManageAlarmManageRepeatState),
+    /// Warning before saving an alarm in conflict, with the selected
+    /// choice, 0 to keep and 1 to discard.
+    SaveConflict(Conflict, usize),
 }
 impl ManageAlarmState {
-    pub fn ok(self, manage: &ManageAlarm, cmds: &mut Vec<Cmd, U4>) -> Screen {
+    pub fn ok(
+        self,
+        manage: &ManageAlarm,
+        manager: &AlarmManager,
+        cmds: &mut Vec<Cmd, U4>,
+    ) -> Screen {
         use self::ManageAlarmState::*;
         match self {
-            Main(state) => state.ok(manage, cmds),
+            Main(state) => state.ok(manage, manager, cmds),
             SetHour => {
                 let mut manage = manage.clone();
                 manage.state = SetMin;
@@ -197,7 +524,122 @@ impl ManageAlarmState {
                 manage.state = Main(ManageAlarmMainState::SetTime);
                 Screen::ManageAlarm(manage)
             }
+            SetYear => {
+                let mut manage = manage.clone();
+                manage.state = SetMonth;
+                Screen::ManageAlarm(manage)
+            }
+            SetMonth => {
+                let mut manage = manage.clone();
+                manage.state = SetDay;
+                Screen::ManageAlarm(manage)
+            }
+            SetDay => {
+                let mut manage = manage.clone();
+                manage.state = Main(ManageAlarmMainState::SetDate);
+                Screen::ManageAlarm(manage)
+            }
+            SetRingDuration => {
+                let mut manage = manage.clone();
+                manage.state = Main(ManageAlarmMainState::RingDuration);
+                Screen::ManageAlarm(manage)
+            }
+            SetEscalateAfter => {
+                let mut manage = manage.clone();
+                manage.state = if manage.alarm.ring_policy.escalate_after == 0 {
+                    Main(ManageAlarmMainState::Escalation)
+                } else {
+                    SetEscalation
+                };
+                Screen::ManageAlarm(manage)
+            }
+            SetEscalation => {
+                let mut manage = manage.clone();
+                manage.state = Main(ManageAlarmMainState::Escalation);
+                Screen::ManageAlarm(manage)
+            }
+            SetPreAlarm => {
+                let mut manage = manage.clone();
+                manage.state = Main(ManageAlarmMainState::PreAlarm);
+                Screen::ManageAlarm(manage)
+            }
+            SetSolarEvent => {
+                let mut manage = manage.clone();
+                manage.state = if manage.alarm.solar().is_none() {
+                    Main(ManageAlarmMainState::SetSolar)
+                } else {
+                    SetSolarOffset
+                };
+                Screen::ManageAlarm(manage)
+            }
+            SetSolarOffset => {
+                let mut manage = manage.clone();
+                manage.state = Main(ManageAlarmMainState::SetSolar);
+                Screen::ManageAlarm(manage)
+            }
+            SetLabel(i) => {
+                let mut manage = manage.clone();
+                // OK on a space ends the label
+                manage.state = if manage.alarm.label.char_at(i) == ' ' {
+                    manage.alarm.label.truncate(i);
+                    Main(ManageAlarmMainState::SetLabel)
+                } else if i + 1 == LABEL_LEN {
+                    Main(ManageAlarmMainState::SetLabel)
+                } else {
+                    SetLabel(i + 1)
+                };
+                Screen::ManageAlarm(manage)
+            }
+            ManageRotation(i) => {
+                let mut manage = manage.clone();
+                manage.state = match i {
+                    0 => SetRotationStart,
+                    i if i <= NB_SLOTS => SetSlotDays(i - 1),
+                    i if i == NB_SLOTS + 1 => {
+                        manage.alarm.set_rotation(None);
+                        Main(ManageAlarmMainState::SetRotation)
+                    }
+                    _ => Main(ManageAlarmMainState::SetRotation),
+                };
+                Screen::ManageAlarm(manage)
+            }
+            SetRotationStart => {
+                let mut manage = manage.clone();
+                manage.state = ManageRotation(0);
+                Screen::ManageAlarm(manage)
+            }
+            SetSlotDays(i) => {
+                let mut manage = manage.clone();
+                let slot = slot(&manage.alarm, i);
+                manage.state = if slot.nb_days == 0 {
+                    ManageRotation(i + 1)
+                } else {
+                    SetSlotHour(i)
+                };
+                Screen::ManageAlarm(manage)
+            }
+            SetSlotHour(i) => {
+                let mut manage = manage.clone();
+                let slot = slot(&manage.alarm, i);
+                manage.state = if slot.time.is_none() {
+                    ManageRotation(i + 1)
+                } else {
+                    SetSlotMin(i)
+                };
+                Screen::ManageAlarm(manage)
+            }
+            SetSlotMin(i) => {
+                let mut manage = manage.clone();
+                manage.state = ManageRotation(i + 1);
+                Screen::ManageAlarm(manage)
+            }
             ManageRepeat(state) => state.ok(manage),
+            SaveConflict(_, 0) => {
+                cmds.push(Cmd::UpdateAlarm(manage.alarm.clone(), manage.id))
+                    .unwrap();
+                Screen::Clock
+            }
+            SaveConflict(..) => Screen::Clock,
         }
     }
     pub fn next(self, alarm: &mut Alarm) -> Self {
@@ -214,7 +656,110 @@ impl ManageAlarmState {
                 alarm.set_min((min + 5) % 60);
                 SetMin
             }
+            SetYear => {
+                edit_date(alarm, |d| {
+                    d.year = if d.year >= MAX_ALARM_YEAR {
+                        MIN_ALARM_YEAR
+                    } else {
+                        d.year + 1
+                    }
+                });
+                SetYear
+            }
+            SetMonth => {
+                edit_date(alarm, |d| d.month = d.month % 12 + 1);
+                SetMonth
+            }
+            SetDay => {
+                edit_date(alarm, |d| d.day = d.day % d.nb_days_in_month() + 1);
+                SetDay
+            }
+            SetRingDuration => {
+                let duration = &mut alarm.ring_policy.duration;
+                *duration = *duration % MAX_RING_DURATION + 1;
+                SetRingDuration
+            }
+            SetEscalateAfter => {
+                let after = &mut alarm.ring_policy.escalate_after;
+                *after = (*after + 1) % MAX_RING_DURATION;
+                SetEscalateAfter
+            }
+            SetEscalation => {
+                alarm.ring_policy.escalation = alarm.ring_policy.escalation.next();
+                SetEscalation
+            }
+            SetPreAlarm => {
+                alarm.pre_alarm = (alarm.pre_alarm + 5) % (MAX_PRE_ALARM + 5);
+                SetPreAlarm
+            }
+            SetSolarEvent => {
+                let solar = match alarm.solar() {
+                    None => Some(Solar {
+                        event: SunEvent::Sunrise,
+                        offset: 0,
+                    }),
+                    Some(solar) if solar.event == SunEvent::Sunrise => Some(Solar {
+                        event: SunEvent::Sunset,
+                        ..solar
+                    }),
+                    Some(_) => None,
+                };
+                alarm.set_solar(solar);
+                SetSolarEvent
+            }
+            SetSolarOffset => {
+                edit_solar(alarm, |s| {
+                    s.offset = if s.offset >= MAX_SOLAR_OFFSET {
+                        -MAX_SOLAR_OFFSET
+                    } else {
+                        s.offset + SOLAR_OFFSET_STEP
+                    }
+                });
+                SetSolarOffset
+            }
+            SetLabel(i) => {
+                alarm.label.next_char(i);
+                SetLabel(i)
+            }
+            ManageRotation(i) => ManageRotation((i + 1) % (NB_SLOTS + 3)),
+            SetRotationStart => {
+                edit_rotation(alarm, |r| r.start = add_days(r.start, 1));
+                SetRotationStart
+            }
+            SetSlotDays(i) => {
+                // the first slot is never empty to keep the cycle valid
+                let min_days = if i == 0 { 1 } else { 0 };
+                edit_rotation(alarm, |r| {
+                    let days = &mut r.slots[i].nb_days;
+                    *days = if *days >= MAX_SLOT_DAYS {
+                        min_days
+                    } else {
+                        *days + 1
+                    };
+                });
+                SetSlotDays(i)
+            }
+            SetSlotHour(i) => {
+                edit_rotation(alarm, |r| {
+                    let time = &mut r.slots[i].time;
+                    *time = match *time {
+                        None => Some((0, 0)),
+                        Some((23, _)) => None,
+                        Some((h, m)) => Some((h + 1, m)),
+                    };
+                });
+                SetSlotHour(i)
+            }
+            SetSlotMin(i) => {
+                edit_rotation(alarm, |r| {
+                    if let Some((_, m)) = &mut r.slots[i].time {
+                        *m = (*m + 5) % 60;
+                    }
+                });
+                SetSlotMin(i)
+            }
             ManageRepeat(state) => ManageRepeat(state.next()),
+            SaveConflict(conflict, i) => SaveConflict(conflict, 1 - i),
         }
     }
     pub fn prev(self, alarm: &mut Alarm) -> Self {
@@ -231,7 +776,112 @@ impl ManageAlarmState {
                 alarm.set_min((min + 60 - 5) % 60);
                 SetMin
             }
+            SetYear => {
+                edit_date(alarm, |d| {
+                    d.year = if d.year <= MIN_ALARM_YEAR {
+                        MAX_ALARM_YEAR
+                    } else {
+                        d.year - 1
+                    }
+                });
+                SetYear
+            }
+            SetMonth => {
+                edit_date(alarm, |d| d.month = (d.month + 12 - 2) % 12 + 1);
+                SetMonth
+            }
+            SetDay => {
+                edit_date(alarm, |d| {
+                    let nb = d.nb_days_in_month();
+                    d.day = (d.day + nb - 2) % nb + 1
+                });
+                SetDay
+            }
+            SetRingDuration => {
+                let duration = &mut alarm.ring_policy.duration;
+                *duration = (*duration + MAX_RING_DURATION - 2) % MAX_RING_DURATION + 1;
+                SetRingDuration
+            }
+            SetEscalateAfter => {
+                let after = &mut alarm.ring_policy.escalate_after;
+                *after = (*after + MAX_RING_DURATION - 1) % MAX_RING_DURATION;
+                SetEscalateAfter
+            }
+            SetEscalation => {
+                alarm.ring_policy.escalation = alarm.ring_policy.escalation.prev();
+                SetEscalation
+            }
+            SetPreAlarm => {
+                alarm.pre_alarm = (alarm.pre_alarm + MAX_PRE_ALARM) % (MAX_PRE_ALARM + 5);
+                SetPreAlarm
+            }
+            SetSolarEvent => {
+                let solar = match alarm.solar() {
+                    None => Some(Solar {
+                        event: SunEvent::Sunset,
+                        offset: 0,
+                    }),
+                    Some(solar) if solar.event == SunEvent::Sunset => Some(Solar {
+                        event: SunEvent::Sunrise,
+                        ..solar
+                    }),
+                    Some(_) => None,
+                };
+                alarm.set_solar(solar);
+                SetSolarEvent
+            }
+            SetSolarOffset => {
+                edit_solar(alarm, |s| {
+                    s.offset = if s.offset <= -MAX_SOLAR_OFFSET {
+                        MAX_SOLAR_OFFSET
+                    } else {
+                        s.offset - SOLAR_OFFSET_STEP
+                    }
+                });
+                SetSolarOffset
+            }
+            SetLabel(i) => {
+                alarm.label.prev_char(i);
+                SetLabel(i)
+            }
+            ManageRotation(i) => ManageRotation((i + NB_SLOTS + 2) % (NB_SLOTS + 3)),
+            SetRotationStart => {
+                edit_rotation(alarm, |r| r.start = add_days(r.start, -1));
+                SetRotationStart
+            }
+            SetSlotDays(i) => {
+                let min_days = if i == 0 { 1 } else { 0 };
+                edit_rotation(alarm, |r| {
+                    let days = &mut r.slots[i].nb_days;
+                    *days = if *days <= min_days {
+                        MAX_SLOT_DAYS
+                    } else {
+                        *days - 1
+                    };
+                });
+                SetSlotDays(i)
+            }
+            SetSlotHour(i) => {
+                edit_rotation(alarm, |r| {
+                    let time = &mut r.slots[i].time;
+                    *time = match *time {
+                        None => Some((23, 0)),
+                        Some((0, _)) => None,
+                        Some((h, m)) => Some((h - 1, m)),
+                    };
+                });
+                SetSlotHour(i)
+            }
+            SetSlotMin(i) => {
+                edit_rotation(alarm, |r| {
+                    if let Some((_, m)) = &mut r.slots[i].time {
+                        *m = (*m + 55) % 60;
+                    }
+                });
+                SetSlotMin(i)
+            }
             ManageRepeat(state) => ManageRepeat(state.prev()),
+            SaveConflict(conflict, i) => SaveConflict(conflict, 1 - i),
         }
     }
     pub fn cancel(self) -> Option<Self> {
@@ -240,31 +890,184 @@ impl ManageAlarmState {
             Main(..) => None,
             SetHour => Some(Main(ManageAlarmMainState::SetTime)),
             SetMin => Some(SetHour),
+            SetYear => Some(Main(ManageAlarmMainState::SetDate)),
+            SetMonth => Some(SetYear),
+            SetDay => Some(SetMonth),
+            SetRingDuration => Some(Main(ManageAlarmMainState::RingDuration)),
+            SetEscalateAfter => Some(Main(ManageAlarmMainState::Escalation)),
+            SetEscalation => Some(SetEscalateAfter),
+            SetPreAlarm => Some(Main(ManageAlarmMainState::PreAlarm)),
+            SetSolarEvent => Some(Main(ManageAlarmMainState::SetSolar)),
+            SetSolarOffset => Some(SetSolarEvent),
+            SetLabel(0) => Some(Main(ManageAlarmMainState::SetLabel)),
+            SetLabel(i) => Some(SetLabel(i - 1)),
+            ManageRotation(..) => Some(Main(ManageAlarmMainState::SetRotation)),
+            SetRotationStart => Some(ManageRotation(0)),
+            SetSlotDays(i) => Some(ManageRotation(i + 1)),
+            SetSlotHour(i) => Some(SetSlotDays(i)),
+            SetSlotMin(i) => Some(SetSlotHour(i)),
             ManageRepeat(..) => Some(Main(ManageAlarmMainState::ManageRepeat)),
+            SaveConflict(..) => Some(Main(ManageAlarmMainState::Quit)),
         }
     }
     pub fn render(self, alarm: &Alarm, display: &mut Display2in9) {
         use self::ManageAlarmState::*;
 
         let mut title = String::<U40>::new();
-        write!(title, "Edit: {}", alarm).unwrap();
+        write_truncated(&mut title, format_args!("Edit: {}", alarm));
+        let policy = alarm.ring_policy;
+        let mut s = String::<U40>::new();
         match self {
             Main(state) => {
+                let mut ringtone = String::<U40>::new();
+                write!(ringtone, "Ringtone: {}", alarm.ringtone).unwrap();
+                let mut label = String::<U40>::new();
+                if alarm.label.is_empty() {
+                    write!(label, "Set label").unwrap();
+                } else {
+                    write!(label, "Label: {}", alarm.label).unwrap();
+                }
+                let mut rotation = String::<U40>::new();
+                match alarm.rotation() {
+                    None => write!(rotation, "Set rotation").unwrap(),
+                    Some(r) => write!(rotation, "Rotation: {} days", r.nb_days()).unwrap(),
+                }
+                let mut duration = String::<U40>::new();
+                write!(duration, "Ring duration: {} min", policy.duration).unwrap();
+                let mut pre_alarm = String::<U40>::new();
+                write!(pre_alarm, "Pre-alarm: {}", PreAlarm(alarm.pre_alarm)).unwrap();
+                let mut solar = String::<U40>::new();
+                write!(solar, "Solar time: {}", SolarTime(alarm.solar())).unwrap();
+                let mut challenge = String::<U40>::new();
+                write!(challenge, "Dismiss challenge: {}", policy.challenge).unwrap();
+                let mut holidays = String::<U40>::new();
+                match alarm.holidays {
+                    None => holidays.push_str("Skip holidays: off").unwrap(),
+                    Some(country) => write!(holidays, "Skip holidays: {}", country).unwrap(),
+                }
+                if policy.escalate_after == 0 {
+                    write!(s, "Escalation: never").unwrap();
+                } else {
+                    write!(
+                        s,
+                        "Escalation: {} min, {}",
+                        policy.escalate_after, policy.escalation
+                    )
+                    .unwrap();
+                }
                 let menu = [
                     if alarm.is_enable { "Disable" } else { "Enable" },
                     "Set Time",
+                    &solar,
+                    &label,
                     if alarm.mode.contains(Mode::ONE_TIME) {
                         "Repeat"
                     } else {
                         "One time"
                     },
+                    if alarm.date().is_some() {
+                        "Change date"
+                    } else {
+                        "Set date"
+                    },
                     "Manage repeat",
+                    &rotation,
+                    if alarm.skip_next {
+                        "Don't skip next"
+                    } else {
+                        "Skip next"
+                    },
+                    &holidays,
+                    &ringtone,
+                    &duration,
+                    &s,
+                    &pre_alarm,
+                    &challenge,
                     "Save and quit",
                 ];
                 menu::render(&title, &menu, state as i32, display);
             }
             SetHour => menu::render(&title, &["Set hour"], 0, display),
             SetMin => menu::render(&title, &["Set minute"], 0, display),
+            SetYear => menu::render(&title, &["Set year"], 0, display),
+            SetMonth => menu::render(&title, &["Set month"], 0, display),
+            SetDay => menu::render(&title, &["Set day"], 0, display),
+            SetRingDuration => {
+                write!(s, "Ring duration: {} min", policy.duration).unwrap();
+                menu::render(&title, &[&s], 0, display);
+            }
+            SetEscalateAfter => {
+                if policy.escalate_after == 0 {
+                    write!(s, "Escalate after: never").unwrap();
+                } else {
+                    write!(s, "Escalate after: {} min", policy.escalate_after).unwrap();
+                }
+                menu::render(&title, &[&s], 0, display);
+            }
+            SetEscalation => {
+                write!(s, "Escalate to: {}", policy.escalation).unwrap();
+                menu::render(&title, &[&s], 0, display);
+            }
+            SetPreAlarm => {
+                write!(s, "Pre-alarm: {}", PreAlarm(alarm.pre_alarm)).unwrap();
+                menu::render(&title, &[&s], 0, display);
+            }
+            SetSolarEvent => {
+                let event = alarm.solar().map(|s| s.event);
+                match event {
+                    None => write!(s, "Relative to: nothing").unwrap(),
+                    Some(event) => write!(s, "Relative to: {}", event).unwrap(),
+                }
+                menu::render(&title, &[&s], 0, display);
+            }
+            SetSolarOffset => {
+                let offset = alarm.solar().map_or(0, |s| s.offset);
+                write!(s, "Offset: {:+} min", offset).unwrap();
+                menu::render(&title, &[&s], 0, display);
+            }
+            SetLabel(pos) => {
+                s.push_str("Label: ").unwrap();
+                write_edited_label(&mut s, &alarm.label, pos);
+                menu::render(&title, &[&s, "OK on a space to end"], 0, display);
+            }
+            ManageRotation(i) => {
+                let rotation = match alarm.rotation() {
+                    Some(rotation) => rotation,
+                    None => return,
+                };
+                let mut items: Vec<String<U40>, U10> = Vec::new();
+                write!(s, "Start: {}", rotation.start).unwrap();
+                items.push(s).unwrap();
+                for (i, slot) in rotation.slots.iter().enumerate() {
+                    let mut s = String::new();
+                    if slot.nb_days == 0 {
+                        write!(s, "Slot {}: unused", i + 1).unwrap();
+                    } else {
+                        write!(s, "Slot {}: {}", i + 1, slot).unwrap();
+                    }
+                    items.push(s).unwrap();
+                }
+                let mut items: Vec<&str, U10> = items.iter().map(|s| s.as_str()).collect();
+                items.push("Remove rotation").unwrap();
+                items.push("Back").unwrap();
+                menu::render(&title, &items, i as i32, display);
+            }
+            SetRotationStart => {
+                if let Some(rotation) = alarm.rotation() {
+                    write!(s, "Start: {}", rotation.start).unwrap();
+                }
+                menu::render(&title, &[&s], 0, display);
+            }
+            SetSlotDays(i) | SetSlotHour(i) | SetSlotMin(i) => {
+                let slot = slot(alarm, i);
+                let field = match self {
+                    SetSlotDays(_) => "days",
+                    SetSlotHour(_) => "hour",
+                    _ => "minute",
+                };
+                write!(s, "Slot {} {}: {}", i + 1, field, slot).unwrap();
+                menu::render(&title, &[&s], 0, display);
+            }
             ManageRepeat(state) => {
                 let menu = [
                     manage_str!(alarm, Monday, MONDAY),
@@ -278,6 +1081,17 @@ impl ManageAlarmState {
                 ];
                 menu::render(&title, &menu, state as i32, display);
             }
+            SaveConflict(conflict, i) => {
+                let mut title = String::<U40>::new();
+                match conflict {
+                    Conflict::Duplicate(j) => write!(title, "Duplicate of alarm {}!", j + 1),
+                    Conflict::Near(j) => {
+                        write!(title, "Within {} min of alarm {}!", CONFLICT_MIN, j + 1)
+                    }
+                }
+                .unwrap();
+                menu::render(&title, &["Keep", "Discard"], i as i32, display);
+            }
         }
     }
 }
@@ -285,12 +1099,28 @@ impl ManageAlarmState {
 enum ManageAlarmMainState {
     ToggleEnable,
     SetTime,
+    SetSolar,
+    SetLabel,
     ToggleOneTime,
+    SetDate,
     ManageRepeat,
+    SetRotation,
+    ToggleSkipNext,
+    SkipHolidays,
+    SetRingtone,
+    RingDuration,
+    Escalation,
+    PreAlarm,
+    Challenge,
     Quit,
 }
 impl ManageAlarmMainState {
-    pub fn ok(self, manage: &ManageAlarm, cmds: &mut Vec<Cmd, U4>) -> Screen {
+    pub fn ok(
+        self,
+        manage: &ManageAlarm,
+        manager: &AlarmManager,
+        cmds: &mut Vec<Cmd, U4>,
+    ) -> Screen {
         use self::ManageAlarmMainState::*;
         match self {
             ToggleEnable => {
@@ -303,9 +1133,31 @@ impl ManageAlarmMainState {
                 manage.state = ManageAlarmState::SetHour;
                 Screen::ManageAlarm(manage)
             }
+            SetSolar => {
+                let mut manage = manage.clone();
+                manage.state = ManageAlarmState::SetSolarEvent;
+                Screen::ManageAlarm(manage)
+            }
+            SetLabel => {
+                let mut manage = manage.clone();
+                manage.state = ManageAlarmState::SetLabel(0);
+                Screen::ManageAlarm(manage)
+            }
             ToggleOneTime => {
                 let mut manage = manage.clone();
                 manage.alarm.mode.toggle(Mode::ONE_TIME);
+                if !manage.alarm.mode.contains(Mode::ONE_TIME) {
+                    manage.alarm.set_date(None);
+                }
+                Screen::ManageAlarm(manage)
+            }
+            SetDate => {
+                let mut manage = manage.clone();
+                if manage.alarm.date().is_none() {
+                    let today = manage.today;
+                    manage.alarm.set_date(Some(today));
+                }
+                manage.state = ManageAlarmState::SetYear;
                 Screen::ManageAlarm(manage)
             }
             ManageRepeat => {
@@ -314,20 +1166,91 @@ impl ManageAlarmMainState {
                 manage.state = ManageAlarmState::ManageRepeat(Monday);
                 Screen::ManageAlarm(manage)
             }
-            Quit => {
-                cmds.push(Cmd::UpdateAlarm(manage.alarm.clone(), manage.id))
+            SetRotation => {
+                let mut manage = manage.clone();
+                if manage.alarm.rotation().is_none() {
+                    let mut rotation = Rotation::new(manage.today);
+                    let time = Some((manage.alarm.hour(), manage.alarm.min()));
+                    rotation.slots[0] = Slot { nb_days: 4, time };
+                    rotation.slots[1] = Slot {
+                        nb_days: 4,
+                        time: None,
+                    };
+                    manage.alarm.set_rotation(Some(rotation));
+                }
+                manage.state = ManageAlarmState::ManageRotation(0);
+                Screen::ManageAlarm(manage)
+            }
+            ToggleSkipNext => {
+                let mut manage = manage.clone();
+                manage.alarm.skip_next = !manage.alarm.skip_next;
+                Screen::ManageAlarm(manage)
+            }
+            SkipHolidays => {
+                let mut manage = manage.clone();
+                manage.alarm.holidays = Country::next(manage.alarm.holidays);
+                Screen::ManageAlarm(manage)
+            }
+            SetRingtone => {
+                let mut manage = manage.clone();
+                manage.alarm.ringtone = manage.alarm.ringtone.next();
+                cmds.push(Cmd::PreviewRingtone(manage.alarm.ringtone))
                     .unwrap();
-                Screen::Clock
+                Screen::ManageAlarm(manage)
             }
+            RingDuration => {
+                let mut manage = manage.clone();
+                manage.state = ManageAlarmState::SetRingDuration;
+                Screen::ManageAlarm(manage)
+            }
+            Escalation => {
+                let mut manage = manage.clone();
+                manage.state = ManageAlarmState::SetEscalateAfter;
+                Screen::ManageAlarm(manage)
+            }
+            PreAlarm => {
+                let mut manage = manage.clone();
+                manage.state = ManageAlarmState::SetPreAlarm;
+                Screen::ManageAlarm(manage)
+            }
+            Challenge => {
+                let mut manage = manage.clone();
+                let challenge = &mut manage.alarm.ring_policy.challenge;
+                *challenge = challenge.next();
+                Screen::ManageAlarm(manage)
+            }
+            Quit => match manager.conflict(manage.id, &manage.alarm, manage.today) {
+                Some(conflict) => {
+                    let mut manage = manage.clone();
+                    manage.state = ManageAlarmState::SaveConflict(conflict, 0);
+                    Screen::ManageAlarm(manage)
+                }
+                None => {
+                    cmds.push(Cmd::UpdateAlarm(manage.alarm.clone(), manage.id))
+                        .unwrap();
+                    Screen::Clock
+                }
+            },
         }
     }
     pub fn next(self) -> Self {
         use self::ManageAlarmMainState::*;
         match self {
             ToggleEnable => SetTime,
-            SetTime => ToggleOneTime,
-            ToggleOneTime => ManageRepeat,
-            ManageRepeat => Quit,
+            SetTime => SetSolar,
+            SetSolar => SetLabel,
+            SetLabel => ToggleOneTime,
+            ToggleOneTime => SetDate,
+            SetDate => ManageRepeat,
+            ManageRepeat => SetRotation,
+            SetRotation => ToggleSkipNext,
+            ToggleSkipNext => SkipHolidays,
+            SkipHolidays => SetRingtone,
+            SetRingtone => RingDuration,
+            RingDuration => Escalation,
+            Escalation => PreAlarm,
+            PreAlarm => Challenge,
+            Challenge => Quit,
             Quit => ToggleEnable,
         }
     }
@@ -336,9 +1259,20 @@ impl ManageAlarmMainState {
         match self {
             ToggleEnable => Quit,
             SetTime => ToggleEnable,
-            ToggleOneTime => SetTime,
-            ManageRepeat => ToggleOneTime,
-            Quit => ManageRepeat,
+            SetSolar => SetTime,
+            SetLabel => SetSolar,
+            ToggleOneTime => SetLabel,
+            SetDate => ToggleOneTime,
+            ManageRepeat => SetDate,
+            SetRotation => ManageRepeat,
+            ToggleSkipNext => SetRotation,
+            SkipHolidays => ToggleSkipNext,
+            SetRingtone => SkipHolidays,
+            RingDuration => SetRingtone,
+            Escalation => RingDuration,
+            PreAlarm => Escalation,
+            Challenge => PreAlarm,
+            Quit => Challenge,
         }
     }
 }
@@ -0,0 +1,103 @@
+use core::ptr;
+use portable::store::{self, Flash};
+use stm32f1xx_hal::stm32;
+
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xcdef_89ab;
+const PAGE_SIZE: usize = 1024;
+/// Pages of a bank of the store.
+const BANK_PAGES: usize = 2;
+const NB_PAGES: usize = 2 * BANK_PAGES;
+
+/// The last pages of the internal flash, that must be excluded from
+/// the `FLASH` region of `memory.x`, as the 2 banks of the store.
+pub struct Pages {
+    address: usize,
+}
+impl Pages {
+    /// The last pages of the 64KiB of flash.
+    pub fn last() -> Self {
+        Self {
+            address: 0x0800_0000 + (64 - NB_PAGES) * PAGE_SIZE,
+        }
+    }
+    fn regs(&self) -> &stm32::flash::RegisterBlock {
+        // the HAL only uses ACR, and the other registers are only
+        // accessed by these pages
+        unsafe { &*stm32::FLASH::ptr() }
+    }
+    fn unlock(&self) {
+        let regs = self.regs();
+        regs.keyr.write(|w| unsafe { w.key().bits(KEY1) });
+        regs.keyr.write(|w| unsafe { w.key().bits(KEY2) });
+    }
+    fn lock(&self) {
+        self.regs().cr.modify(|_, w| w.lock().set_bit());
+    }
+    /// Address of the half-word `index` of the bank `bank`.
+    fn half_word(&self, bank: usize, index: usize) -> *const u16 {
+        assert!(bank < 2 && index < self.bank_len());
+        let address = self.address + bank * BANK_PAGES * PAGE_SIZE;
+        (address as *const u16).wrapping_add(index)
+    }
+    /// Waits for the end of the operation, returning an error if it
+    /// failed.
+    fn wait(&self) -> Result<(), store::Error> {
+        let regs = self.regs();
+        while regs.sr.read().bsy().bit_is_set() {}
+        let sr = regs.sr.read();
+        let failed =
+            sr.pgerr().bit_is_set() || sr.wrprterr().bit_is_set() || sr.eop().bit_is_clear();
+        // the flags are cleared by writing 1
+        regs.sr
+            .write(|w| w.eop().set_bit().pgerr().set_bit().wrprterr().set_bit());
+        if failed {
+            Err(store::Error::Flash)
+        } else {
+            Ok(())
+        }
+    }
+}
+impl Flash for Pages {
+    fn bank_len(&self) -> usize {
+        BANK_PAGES * PAGE_SIZE / 2
+    }
+    fn read(&self, bank: usize, index: usize) -> u16 {
+        unsafe { ptr::read_volatile(self.half_word(bank, index)) }
+    }
+    fn write(&mut self, bank: usize, index: usize, value: u16) -> Result<(), store::Error> {
+        let half_word = self.half_word(bank, index) as *mut u16;
+        self.unlock();
+        self.regs().cr.modify(|_, w| w.pg().set_bit());
+        unsafe { ptr::write_volatile(half_word, value) };
+        let res = self.wait();
+        self.regs().cr.modify(|_, w| w.pg().clear_bit());
+        self.lock();
+        res?;
+        // the programming may have silently failed
+        if self.read(bank, index) != value {
+            return Err(store::Error::Flash);
+        }
+        Ok(())
+    }
+    fn erase(&mut self, bank: usize) -> Result<(), store::Error> {
+        assert!(bank < 2);
+        self.unlock();
+        let regs = self.regs();
+        regs.cr.modify(|_, w| w.per().set_bit());
+        let mut res = Ok(());
+        for page in 0..BANK_PAGES {
+            let address = self.address + (bank * BANK_PAGES + page) * PAGE_SIZE;
+            regs.ar.write(|w| unsafe { w.far().bits(address as u32) });
+            regs.cr.modify(|_, w| w.strt().set_bit());
+            res = res.and(self.wait());
+        }
+        regs.cr.modify(|_, w| w.per().clear_bit());
+        self.lock();
+        res?;
+        if (0..self.bank_len()).any(|i| self.read(bank, i) != store::ERASED) {
+            return Err(store::Error::Flash);
+        }
+        Ok(())
+    }
+}
@@ -7,14 +7,26 @@ extern crate panic_semihosting;
 use embedded_hal::digital::v1_compat::{OldInputPin, OldOutputPin};
 use epd_waveshare::prelude::*;
 use portable::datetime::DateTime;
-use portable::{alarm, button, datetime, ui};
-use pwm_speaker::songs::SO_WHAT;
+use portable::{alarm, button, datetime, history, store, ui};
 use rtic::app;
+use stm32f1xx_hal::backup_domain::BackupDomain;
 use stm32f1xx_hal::prelude::*;
 use stm32f1xx_hal::{delay, gpio, i2c, rtc, spi, stm32, timer};
 
+mod flash;
 mod sound;
 
+/// First of the 2 backup data registers saving the nap, surviving a
+/// reset as long as the RTC. They saved the last alarm of the
+/// previous versions, moved to the store at the first start.
+const NAP_REGISTER: usize = 8;
+
+fn save_nap(backup_domain: &mut BackupDomain, manager: &alarm::AlarmManager) {
+    let nap = manager.nap_as_u32();
+    backup_domain.write_data_register_low(NAP_REGISTER, nap as u16);
+    backup_domain.write_data_register_low(NAP_REGISTER + 1, (nap >> 16) as u16);
+}
+
 type I2C = i2c::BlockingI2c<
     stm32::I2C1,
     (
@@ -47,8 +59,10 @@ type EPaperDisplay = epd_waveshare::epd2in9::EPD2in9<
 const APP: () = {
     struct Resources {
         rtc_dev: rtc::Rtc,
+        backup_domain: BackupDomain,
         bme280: bme280::BME280<I2C, delay::Delay>,
         alarm_manager: alarm::AlarmManager,
+        history: history::History,
         sound: sound::Sound,
         button0: button::Button<Button0Pin>,
         button1: button::Button<Button1Pin>,
@@ -60,7 +74,7 @@ const APP: () = {
         #[init(true)]
         full_update: bool,
         timer: timer::CountDownTimer<stm32::TIM3>,
-        backup_domain: stm32f1xx_hal::backup_domain::BackupDomain,
+        store: store::Store<flash::Pages>,
     }
 
     #[init(spawn = [msg])]
@@ -116,14 +130,24 @@ const APP: () = {
         }
         rtc_dev.listen_seconds();
 
-        let mut alarm_manager = alarm::AlarmManager::default();
-        for i in 0..5 {
-            let d0 = backup_domain.read_data_register_low(i * 2);
-            let d1 = backup_domain.read_data_register_low(i * 2 + 1);
-            if let Some(alarm) = alarm::Alarm::try_from(d0 as u32 | (d1 as u32) << 16) {
-                alarm_manager.alarms[i] = alarm;
+        let mut store = store::Store::new(flash::Pages::last());
+        let mut alarm_manager: alarm::AlarmManager = alarm::AlarmManager::load(&store);
+        if store.is_empty() {
+            // alarms saved in the backup domain by the previous versions,
+            // saved in the store before the nap reuses their registers
+            for i in 0..5 {
+                let d0 = backup_domain.read_data_register_low(i * 2);
+                let d1 = backup_domain.read_data_register_low(i * 2 + 1);
+                if let Some(alarm) = alarm::Alarm::try_from(d0 as u32 | (d1 as u32) << 16) {
+                    alarm_manager.alarms[i] = alarm;
+                }
             }
+            let res = store.save(alarm_manager.records());
+            c.spawn.msg(ui::Msg::Saved(res)).unwrap();
         }
+        let nap0 = backup_domain.read_data_register_low(NAP_REGISTER);
+        let nap1 = backup_domain.read_data_register_low(NAP_REGISTER + 1);
+        alarm_manager.load_nap(nap0 as u32 | (nap1 as u32) << 16);
 
         let mut delay = delay::Delay::new(c.core.SYST, clocks);
 
@@ -169,12 +193,11 @@ const APP: () = {
         let mut bme280 = bme280::BME280::new_primary(i2c, delay);
         bme280.init().expect("i2c init error");
 
-        c.spawn
-            .msg(ui::Msg::AlarmManager(alarm_manager.clone()))
-            .unwrap();
+        c.spawn.msg(ui::Msg::AlarmManagerUpdated).unwrap();
 
         init::LateResources {
             rtc_dev,
+            backup_domain,
             bme280,
             sound: sound::Sound::new(speaker),
             button0: button::Button::new(button0_pin),
@@ -185,8 +208,9 @@ const APP: () = {
             spi,
             ui: ui::Model::init(),
             alarm_manager,
+            history: history::History::default(),
             timer,
-            backup_domain,
+            store,
         }
     }
 
@@ -194,9 +218,10 @@ const APP: () = {
     fn tick(c: tick::Context) {
         c.resources.timer.clear_update_interrupt_flag();
 
-        if let button::Event::Pressed = c.resources.button0.poll() {
-            c.resources.sound.stop();
-            c.spawn.msg(ui::Msg::ButtonCancel).unwrap();
+        match c.resources.button0.poll() {
+            button::Event::Pressed => c.spawn.msg(ui::Msg::ButtonCancel).unwrap(),
+            button::Event::Released => c.spawn.msg(ui::Msg::ButtonCancelReleased).unwrap(),
+            _ => {}
         }
         if let button::Event::Pressed = c.resources.button1.poll() {
             c.spawn.msg(ui::Msg::ButtonMinus).unwrap();
@@ -210,17 +235,38 @@ const APP: () = {
         c.resources.sound.poll();
     }
 
-    #[task(binds = RTC, priority = 3, spawn = [msg], resources = [rtc_dev, bme280, alarm_manager, sound])]
+    #[task(binds = RTC, priority = 3, spawn = [msg], resources = [rtc_dev, backup_domain, bme280, alarm_manager, history, sound])]
     fn rtc_task(mut c: rtc_task::Context) {
         c.resources.rtc_dev.clear_second_flag();
 
         let datetime = DateTime::new(c.resources.rtc_dev.current_time());
-        if datetime.sec == 0 && c.resources.alarm_manager.must_ring(&datetime) {
-            c.resources
-                .sound
-                .lock(|alarm| alarm.play(&SO_WHAT, 10 * 60));
-            let manager = c.resources.alarm_manager.clone();
-            c.spawn.msg(ui::Msg::AlarmManager(manager)).unwrap();
+        let manager = &mut *c.resources.alarm_manager;
+        let rings = manager.must_ring(&datetime);
+        if let Some(rings) = &rings {
+            // the nap may have rung
+            save_nap(c.resources.backup_domain, manager);
+            if let Some(now) = datetime.to_epoch() {
+                c.resources.history.ring(rings, now);
+            }
+            let (ringtone, policy) = (manager.ringtone(), manager.ring_policy());
+            c.resources.sound.lock(|alarm| alarm.ring(ringtone, policy));
+        } else if let Some(i) = manager.must_pre_ring(&datetime) {
+            let ringtone = manager.alarms[i].ringtone;
+            c.resources.sound.lock(|alarm| alarm.pre_ring(ringtone));
+        } else if manager.must_remind_bedtime(&datetime) {
+            c.resources.sound.lock(|alarm| alarm.chime());
+        } else if let Some(chime) = manager.must_chime(&datetime) {
+            c.resources.sound.lock(|alarm| alarm.play_chime(chime));
+        }
+        let missed = rings.is_none() && manager.check_missed(&datetime);
+        if let (true, Some(now)) = (missed, datetime.to_epoch()) {
+            c.resources.history.end(now, history::Outcome::Missed);
+        }
+        if rings.is_some() || missed {
+            c.spawn.msg(ui::Msg::AlarmManagerUpdated).unwrap();
+        }
+        if let Some(rings) = rings {
+            c.spawn.msg(ui::Msg::AlarmRinging(rings)).unwrap();
         }
         c.spawn.msg(ui::Msg::DateTime(datetime)).unwrap();
 
@@ -236,10 +282,17 @@ const APP: () = {
         c.spawn.msg(msg).unwrap();
     }
 
-    #[task(priority = 2, capacity = 16, spawn = [msg], resources = [ui, rtc_dev, full_update, alarm_manager, backup_domain])]
+    #[task(priority = 2, capacity = 16, spawn = [msg, snooze, dismiss], resources = [ui, rtc_dev, backup_domain, full_update, alarm_manager, history, store, sound])]
     fn msg(mut c: msg::Context, msg: ui::Msg) {
         use crate::ui::Cmd::*;
-        for cmd in c.resources.ui.update(msg) {
+        let ui = &mut *c.resources.ui;
+        let history = &mut c.resources.history;
+        let cmds = c
+            .resources
+            .alarm_manager
+            .lock(|m| history.lock(|h| ui.update(msg, m, h)));
+        let mut save = false;
+        for cmd in cmds {
             match cmd {
                 UpdateRtc(dt) => {
                     if let Some(epoch) = dt.to_epoch() {
@@ -250,29 +303,146 @@ const APP: () = {
                     }
                 }
                 UpdateAlarm(alarm, i) => {
-                    let data = alarm.as_u32();
+                    c.resources.alarm_manager.lock(|m| m.alarms[i] = alarm);
+                    save = true;
+                    c.spawn.msg(ui::Msg::AlarmManagerUpdated).unwrap();
+                }
+                PreviewRingtone(ringtone) => c.resources.sound.lock(|s| s.preview(ringtone)),
+                UpdateSleepConfig(config) => {
+                    c.resources.alarm_manager.lock(|m| m.sleep_config = config);
+                    save = true;
+                    c.spawn.msg(ui::Msg::AlarmManagerUpdated).unwrap();
+                }
+                Suspend(until) => {
+                    c.resources
+                        .alarm_manager
+                        .lock(|m| m.suspended_until = until);
+                    save = true;
+                    c.spawn.msg(ui::Msg::AlarmManagerUpdated).unwrap();
+                }
+                AddException(exception) => {
+                    c.resources.alarm_manager.lock(|m| {
+                        // the menu adds none when full
+                        let _ = m.exceptions.push(exception);
+                    });
+                    save = true;
+                    c.spawn.msg(ui::Msg::AlarmManagerUpdated).unwrap();
+                }
+                RemoveException(i) => {
+                    c.resources.alarm_manager.lock(|m| m.remove_exception(i));
+                    save = true;
+                    c.spawn.msg(ui::Msg::AlarmManagerUpdated).unwrap();
+                }
+                ActivateProfile(p) => {
+                    // the alarms of the other profiles are only in the
+                    // store, the current ones must be saved before
+                    let manager = c.resources.alarm_manager.lock(|m| m.clone());
+                    match c.resources.store.save(manager.records()) {
+                        Ok(()) => {
+                            let profile: alarm::AlarmManager =
+                                alarm::AlarmManager::load_profile(c.resources.store, p);
+                            c.resources
+                                .alarm_manager
+                                .lock(|m| m.activate_profile(profile));
+                            save = true;
+                        }
+                        Err(e) => c.spawn.msg(ui::Msg::Saved(Err(e))).unwrap(),
+                    }
+                    c.spawn.msg(ui::Msg::AlarmManagerUpdated).unwrap();
+                }
+                RenameProfile(p, name) => {
                     c.resources
-                        .backup_domain
-                        .write_data_register_low(i * 2, data as u16);
+                        .alarm_manager
+                        .lock(|m| m.profiles[usize::from(p)] = name);
+                    save = true;
+                    c.spawn.msg(ui::Msg::AlarmManagerUpdated).unwrap();
+                }
+                UpdateChimeConfig(config) => {
+                    c.resources.alarm_manager.lock(|m| m.chime_config = config);
+                    save = true;
+                    c.spawn.msg(ui::Msg::AlarmManagerUpdated).unwrap();
+                }
+                UpdateLocation(location) => {
                     c.resources
-                        .backup_domain
-                        .write_data_register_low(i * 2 + 1, (data >> 16) as u16);
-                    let manager = c.resources.alarm_manager.lock(|m| {
-                        m.alarms[i] = alarm;
-                        m.clone()
+                        .alarm_manager
+                        .lock(|m| m.location = Some(location));
+                    save = true;
+                    c.spawn.msg(ui::Msg::AlarmManagerUpdated).unwrap();
+                }
+                Nap(minutes) => {
+                    let now = DateTime::new(c.resources.rtc_dev.lock(|rtc| rtc.current_time()));
+                    let backup_domain = &mut c.resources.backup_domain;
+                    c.resources.alarm_manager.lock(|m| {
+                        m.set_nap(&now, minutes);
+                        backup_domain.lock(|bd| save_nap(bd, m));
                     });
-                    c.spawn.msg(ui::Msg::AlarmManager(manager)).unwrap();
+                    c.spawn.msg(ui::Msg::AlarmManagerUpdated).unwrap();
                 }
+                AcknowledgeMissed => {
+                    c.resources.alarm_manager.lock(|m| m.acknowledge_missed());
+                    c.spawn.msg(ui::Msg::AlarmManagerUpdated).unwrap();
+                }
+                Snooze => {
+                    c.resources.sound.lock(|s| s.stop());
+                    c.spawn.snooze().unwrap();
+                }
+                Dismiss => {
+                    c.resources.sound.lock(|s| s.stop());
+                    c.spawn.dismiss().unwrap();
+                }
+                StopSound => c.resources.sound.lock(|s| {
+                    if !s.is_ringing() {
+                        s.stop()
+                    }
+                }),
                 FullUpdate => *c.resources.full_update = true,
             }
         }
+        if save {
+            // a copy is saved, the flash being too slow to write while
+            // the alarms can't ring
+            let manager = c.resources.alarm_manager.lock(|m| m.clone());
+            let res = c.resources.store.save(manager.records());
+            c.spawn.msg(ui::Msg::Saved(res)).unwrap();
+        }
         rtic::pend(stm32::Interrupt::EXTI1);
     }
 
-    #[task(binds = EXTI1, priority = 1, resources = [ui, display, spi, full_update])]
+    #[task(priority = 2, spawn = [msg], resources = [rtc_dev, alarm_manager, history])]
+    fn snooze(mut c: snooze::Context) {
+        let now = c.resources.rtc_dev.lock(|rtc| rtc.current_time());
+        let snoozed = c
+            .resources
+            .alarm_manager
+            .lock(|m| m.snooze(&DateTime::new(now)));
+        // without any snooze left, the alarm is dismissed
+        let outcome = if snoozed {
+            history::Outcome::Snoozed
+        } else {
+            history::Outcome::Dismissed
+        };
+        c.resources.history.lock(|h| h.end(now, outcome));
+        c.spawn.msg(ui::Msg::AlarmManagerUpdated).unwrap();
+    }
+
+    #[task(priority = 2, spawn = [msg], resources = [rtc_dev, alarm_manager, history])]
+    fn dismiss(mut c: dismiss::Context) {
+        let now = c.resources.rtc_dev.lock(|rtc| rtc.current_time());
+        c.resources.alarm_manager.lock(|m| m.dismiss());
+        c.resources
+            .history
+            .lock(|h| h.end(now, history::Outcome::Dismissed));
+        c.spawn.msg(ui::Msg::AlarmManagerUpdated).unwrap();
+    }
+
+    #[task(binds = EXTI1, priority = 1, resources = [ui, alarm_manager, history, display, spi, full_update])]
     fn render(mut c: render::Context) {
         let model = c.resources.ui.lock(|model| model.clone());
-        let display = model.view();
+        let history = &mut c.resources.history;
+        let display = c
+            .resources
+            .alarm_manager
+            .lock(|m| history.lock(|h| model.view(m, h)));
         let full_update = c
             .resources
             .full_update
@@ -1,4 +1,21 @@
-use pwm_speaker::{songs, Speaker};
+use portable::alarm::{RingPolicy, Ringtone};
+use portable::chime::{Chime, Tone};
+use pwm_speaker::{pitch, songs, Speaker};
+
+const PREVIEW_SEC: u32 = 5;
+const PRE_ALARM_SEC: u32 = 3;
+const CHIME_SEC: u32 = 2;
+/// The staccato notes are cut short to be quieter, unit: ms.
+const STACCATO_NOTE_MS: u32 = 30;
+
+pub fn score(ringtone: Ringtone) -> &'static songs::Score {
+    match ringtone {
+        Ringtone::SoWhat => &songs::SO_WHAT,
+        Ringtone::MarioThemeIntro => &songs::MARIO_THEME_INTRO,
+        Ringtone::ThirdKind => &songs::THIRD_KIND,
+        Ringtone::FrereJacques => &songs::FRERE_JACQUES,
+    }
+}
 
 struct IterNb<I> {
     iter: I,
@@ -30,9 +47,47 @@ impl<I: Iterator + Clone> Iterator for IterNb<I> {
     }
 }
 
+fn looped(song: &'static songs::Score, ms: u32) -> IterNb<songs::MsEvents> {
+    let song_ms = song.ms_duration();
+    let nb = if song_ms == 0 { 0 } else { ms / song_ms };
+    IterNb::new(nb as usize, song.ms_events())
+}
+
+fn pitch(tone: Tone) -> pitch::Pitch {
+    match tone {
+        Tone::E5 => pitch::E5,
+        Tone::D5 => pitch::D5,
+        Tone::C5 => pitch::C5,
+        Tone::G4 => pitch::G4,
+        Tone::C4 => pitch::C4,
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Chiming {
+    chime: Chime,
+    /// index of the next note
+    next: usize,
+    /// remaining duration of the current note, unit: ms
+    note_ms: u16,
+}
+
+#[derive(Clone, Copy)]
+struct Ringing {
+    ringtone: Ringtone,
+    policy: RingPolicy,
+    current: Ringtone,
+    elapsed_ms: u32,
+}
+
 pub struct Sound {
     speaker: Speaker,
     playing: bool,
+    ringing: Option<Ringing>,
+    /// duration of the current staccato note, unit: ms
+    staccato_note_ms: Option<u32>,
+    chiming: Option<Chiming>,
+    remaining_ms: u32,
     song: IterNb<songs::MsEvents>,
 }
 impl Sound {
@@ -40,22 +95,75 @@ impl Sound {
         Self {
             speaker,
             playing: false,
+            ringing: None,
+            staccato_note_ms: None,
+            chiming: None,
+            remaining_ms: 0,
             song: IterNb::new(0, songs::MARIO_THEME_INTRO.ms_events()),
         }
     }
-    pub fn play(&mut self, song: &'static songs::Score, nb_sec: u32) {
-        let song_ms = song.ms_duration();
-        let nb = if song_ms == 0 {
-            0
-        } else {
-            nb_sec * 1000 / song_ms
-        };
-        self.song = IterNb::new(nb as usize, song.ms_events());
+    fn play(&mut self, song: &'static songs::Score, nb_sec: u32) {
+        self.song = looped(song, nb_sec * 1000);
         self.playing = true;
+        self.ringing = None;
+        self.staccato_note_ms = None;
+        self.chiming = None;
+        self.remaining_ms = nb_sec * 1000;
         self.speaker.unmute();
     }
+    /// Rings an alarm, following its ring policy.
+    pub fn ring(&mut self, ringtone: Ringtone, policy: RingPolicy) {
+        self.play(score(ringtone), policy.duration_sec());
+        self.ringing = Some(Ringing {
+            ringtone,
+            policy,
+            current: ringtone,
+            elapsed_ms: 0,
+        });
+    }
+    /// Plays the beginning of the ringtone, not as a ringing alarm.
+    pub fn preview(&mut self, ringtone: Ringtone) {
+        self.play(score(ringtone), PREVIEW_SEC);
+    }
+    /// Plays the ringtone staccato for a few seconds, announcing the
+    /// ring. Never interrupts a ringing alarm.
+    pub fn pre_ring(&mut self, ringtone: Ringtone) {
+        self.play_staccato(score(ringtone), PRE_ALARM_SEC);
+    }
+    /// Plays a short reminder. Never interrupts a ringing alarm.
+    pub fn chime(&mut self) {
+        self.play_staccato(&songs::MARIO_THEME_INTRO, CHIME_SEC);
+    }
+    fn play_staccato(&mut self, song: &'static songs::Score, nb_sec: u32) {
+        if self.is_ringing() {
+            return;
+        }
+        self.play(song, nb_sec);
+        self.staccato_note_ms = Some(0);
+    }
+    /// Plays a chime. Never interrupts a ringing alarm.
+    pub fn play_chime(&mut self, chime: Chime) {
+        if self.is_ringing() {
+            return;
+        }
+        self.playing = true;
+        self.ringing = None;
+        self.staccato_note_ms = None;
+        self.chiming = Some(Chiming {
+            chime,
+            next: 0,
+            note_ms: 0,
+        });
+        self.remaining_ms = chime.duration_ms();
+        self.speaker.unmute();
+    }
+    pub fn is_ringing(&self) -> bool {
+        self.playing && self.ringing.is_some()
+    }
     pub fn stop(&mut self) {
         self.playing = false;
+        self.ringing = None;
+        self.chiming = None;
         self.speaker.rest();
         self.speaker.mute();
     }
@@ -63,10 +171,61 @@ impl Sound {
         if !self.playing {
             return;
         }
+        if self.remaining_ms == 0 {
+            self.stop();
+            return;
+        }
+        self.remaining_ms -= 1;
+
+        if let Some(ringing) = self.ringing.as_mut() {
+            let elapsed_sec = ringing.elapsed_ms / 1000;
+            ringing.elapsed_ms += 1;
+            match ringing.policy.ringtone_at(ringing.ringtone, elapsed_sec) {
+                Some(ringtone) if ringtone != ringing.current => {
+                    ringing.current = ringtone;
+                    self.song = looped(score(ringtone), self.remaining_ms);
+                    self.speaker.rest();
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(note_ms) = self.staccato_note_ms.as_mut() {
+            *note_ms += 1;
+            if *note_ms == STACCATO_NOTE_MS {
+                self.speaker.rest();
+            }
+        }
+
+        if let Some(chiming) = self.chiming.as_mut() {
+            if chiming.note_ms == 0 {
+                let note = chiming.chime.note(chiming.next);
+                chiming.next += 1;
+                match note {
+                    Some(note) => {
+                        chiming.note_ms = note.ms;
+                        match note.tone {
+                            Some(tone) => self.speaker.play(pitch(tone)),
+                            None => self.speaker.rest(),
+                        }
+                    }
+                    None => self.stop(),
+                }
+            }
+            if let Some(chiming) = self.chiming.as_mut() {
+                chiming.note_ms = chiming.note_ms.saturating_sub(1);
+            }
+            return;
+        }
 
         use pwm_speaker::songs::MsEvent::*;
         match self.song.next() {
-            Some(BeginNote { pitch }) => self.speaker.play(pitch),
+            Some(BeginNote { pitch }) => {
+                if let Some(note_ms) = self.staccato_note_ms.as_mut() {
+                    *note_ms = 0;
+                }
+                self.speaker.play(pitch)
+            }
             Some(EndNote) => self.speaker.rest(),
             Some(Wait) => (),
             None => self.stop(),